@@ -4,7 +4,7 @@ use common::*;
 
 const WINDOW_TITLE: &str = "Chapter 10";
 
-fn render(canvas: &Canvas) -> anyhow::Result<()> {
+fn scene() -> Scene {
     let mut scene = Scene::default();
     scene.add_instance(Instance::new_cube(Transform::new(
         Vec3::new(-1.5, 0.0, 7.0),
@@ -16,13 +16,41 @@ fn render(canvas: &Canvas) -> anyhow::Result<()> {
         Quat::from_rotation_y(195.0_f32.to_radians()),
         1.0,
     )));
+    scene
+}
 
-    let camera = Camera::new(
+fn camera() -> Camera {
+    Camera::new(
         Vec3::new(-3.0, 1.0, -2.0),
         Quat::from_rotation_y(-30.0_f32.to_radians()),
-    );
+    )
+}
+
+/// Packs both eyes of a stereo rig into the window when set
+const STEREO: Option<StereoLayout> = None;
+
+fn render(canvas: &Canvas) -> anyhow::Result<()> {
+    let scene = scene();
+
+    match STEREO {
+        Some(layout) => {
+            // converged between the cubes
+            let rig = StereoRig::new(camera(), 0.2, 7.0);
+
+            // rasterize each eye and read it back
+            let mut eyes = Vec::with_capacity(Eye::BOTH.len());
+            for eye in Eye::BOTH {
+                canvas.clear(Rgb::BLACK.into());
+                scene.render(canvas, &rig.get_eye(eye))?;
+                eyes.push(canvas.capture()?);
+            }
 
-    scene.render(canvas, &camera)?;
+            let mut framebuffer = Framebuffer::from_canvas(canvas);
+            layout.compose(&eyes[0], &eyes[1], &mut framebuffer);
+            framebuffer.present(canvas)?;
+        }
+        None => scene.render(canvas, &camera())?,
+    }
 
     Ok(())
 }
@@ -1,5 +1,4 @@
-use glam::{IVec3, Vec3};
-use sdl2::pixels::Color;
+use glam::Vec3;
 
 use common::*;
 
@@ -9,38 +8,50 @@ const SPHERES: &[Shape] = &[
     Shape::new_sphere(
         Vec3::new(0.0, -1.0, 3.0),
         1.0,
-        Material::new(Color::RED, None, None),
+        Material::new(Rgb::RED, None, None),
     ),
     Shape::new_sphere(
         Vec3::new(2.0, 0.0, 4.0),
         1.0,
-        Material::new(Color::BLUE, None, None),
+        Material::new(Rgb::BLUE, None, None),
     ),
     Shape::new_sphere(
         Vec3::new(-2.0, 0.0, 4.0),
         1.0,
-        Material::new(Color::GREEN, None, None),
+        Material::new(Rgb::GREEN, None, None),
     ),
 ];
 
+const BACKGROUND: Environment = Environment::color(Rgb::WHITE);
+
+// one sample through each pixel center like the book, e.g.
+// Antialiasing::new(4, SamplePattern::Stratified, ReconstructionFilter::Tent).with_adaptive(0.1)
+// supersamples the edges
+const ANTIALIASING: Antialiasing =
+    Antialiasing::new(1, SamplePattern::Regular, ReconstructionFilter::Box);
+
 fn render(canvas: &Canvas) -> anyhow::Result<()> {
     let camera = Camera::default();
 
+    let mut framebuffer = Framebuffer::from_canvas(canvas);
+
     // TODO: parallelize this
-    for x in -canvas.get_half_width()..=canvas.get_half_width() {
-        for y in -canvas.get_half_height()..=canvas.get_half_height() {
-            let direction = camera.get_rotation() * canvas.to_viewport(x, y);
-            let color = trace_ray_no_lights(
-                camera.get_translation().as_dvec3(),
-                direction.as_dvec3(),
-                1.0,
-                INFINITY,
-                SPHERES,
-                Color::WHITE,
-            );
-            canvas.put_pixel(IVec3::new(x, y, 1), color)?;
-        }
-    }
+    ANTIALIASING.render(&mut framebuffer, |x, y| {
+        let Some((origin, direction)) = camera.get_ray(canvas.to_viewport_subpixel(x, y)) else {
+            return Rgb::BLACK;
+        };
+        trace_ray_no_lights(
+            origin,
+            direction,
+            1.0,
+            INFINITY,
+            camera.sample_time(),
+            SPHERES,
+            &BACKGROUND,
+        )
+    });
+
+    framebuffer.present(canvas)?;
 
     Ok(())
 }
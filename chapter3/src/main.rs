@@ -1,5 +1,4 @@
-use glam::{IVec3, Vec3};
-use sdl2::pixels::Color;
+use glam::Vec3;
 
 use common::*;
 
@@ -9,51 +8,81 @@ const SPHERES: &[Shape] = &[
     Shape::new_sphere(
         Vec3::new(0.0, -1.0, 3.0),
         1.0,
-        Material::new(Color::RED, Some(500.0), None),
+        Material::new(Rgb::RED, Some(500.0), None),
     ),
     Shape::new_sphere(
         Vec3::new(2.0, 0.0, 4.0),
         1.0,
-        Material::new(Color::BLUE, Some(500.0), None),
+        Material::new(Rgb::BLUE, Some(500.0), None),
     ),
     Shape::new_sphere(
         Vec3::new(-2.0, 0.0, 4.0),
         1.0,
-        Material::new(Color::GREEN, Some(10.0), None),
+        Material::new(Rgb::GREEN, Some(10.0), None),
     ),
     Shape::new_sphere(
         Vec3::new(0.0, -5001.0, 0.0),
         5000.0,
-        Material::new(Color::YELLOW, Some(1000.0), None),
+        Material::new_textured(
+            Texture::Procedural(ProceduralTexture::checker(
+                Rgb::YELLOW,
+                Rgb::new(0.5, 0.5, 0.0),
+                TextureSpace::World,
+                1.0,
+            )),
+            Some(1000.0),
+            None,
+        ),
     ),
 ];
 
 const LIGHTS: &[Light] = &[
-    Light::new_ambient(0.2),
-    Light::new_point(0.6, Vec3::new(2.0, 1.0, 0.0)),
-    Light::new_directional(0.2, Vec3::new(1.0, 4.0, 4.0)),
+    Light::new_ambient(Rgb::splat(0.2)),
+    Light::new_point(Rgb::splat(0.6), Vec3::new(2.0, 1.0, 0.0)),
+    Light::new_directional(Rgb::splat(0.2), Vec3::new(1.0, 4.0, 4.0)),
 ];
 
+const BACKGROUND: Environment = Environment::color(Rgb::WHITE);
+
+const MEDIA: Media = Media::new();
+
+// 1 spp like the book, chapter 2 shows how to supersample
+const ANTIALIASING: Antialiasing =
+    Antialiasing::new(1, SamplePattern::Regular, ReconstructionFilter::Box);
+
+/// Clamp matches the book, AcesFilmic compresses bright highlights instead
+const TONE_MAPPING: ToneMapping = ToneMapping::Clamp;
+
+/// Encodes the output as sRGB, off to match the book
+const GAMMA_CORRECTION: bool = false;
+
 fn render(canvas: &Canvas) -> anyhow::Result<()> {
     let camera = Camera::default();
 
+    let mut framebuffer = Framebuffer::from_canvas(canvas)
+        .with_tone_mapping(TONE_MAPPING)
+        .with_gamma_correction(GAMMA_CORRECTION);
+
     // TODO: parallelize this
-    for x in -canvas.get_half_width()..=canvas.get_half_width() {
-        for y in -canvas.get_half_height()..=canvas.get_half_height() {
-            let direction = camera.get_rotation() * canvas.to_viewport(x, y);
-            let color = trace_ray(
-                camera.get_translation().as_dvec3(),
-                direction.as_dvec3(),
-                1.0,
-                INFINITY,
-                0,
-                LIGHTS,
-                SPHERES,
-                Color::WHITE,
-            );
-            canvas.put_pixel(IVec3::new(x, y, 1), color)?;
-        }
-    }
+    ANTIALIASING.render(&mut framebuffer, |x, y| {
+        let Some((origin, direction)) = camera.get_ray(canvas.to_viewport_subpixel(x, y)) else {
+            return Rgb::BLACK;
+        };
+        trace_ray(
+            origin,
+            direction,
+            1.0,
+            INFINITY,
+            camera.sample_time(),
+            0,
+            LIGHTS,
+            SPHERES,
+            &BACKGROUND,
+            &MEDIA,
+        )
+    });
+
+    framebuffer.present(canvas)?;
 
     Ok(())
 }
@@ -1,5 +1,6 @@
-use glam::{IVec3, Vec3};
-use sdl2::pixels::Color;
+use std::sync::{Arc, OnceLock};
+
+use glam::Vec3;
 
 use common::*;
 
@@ -9,53 +10,125 @@ const SPHERES: &[Shape] = &[
     Shape::new_sphere(
         Vec3::new(0.0, -1.0, 3.0),
         1.0,
-        Material::new(Color::RED, Some(500.0), Some(0.2)),
+        Material::new(Rgb::RED, Some(500.0), Some(0.2)),
     ),
     Shape::new_sphere(
         Vec3::new(2.0, 0.0, 4.0),
         1.0,
-        Material::new(Color::BLUE, Some(500.0), Some(0.3)),
+        Material::new(Rgb::BLUE, Some(500.0), Some(0.3)),
     ),
     Shape::new_sphere(
         Vec3::new(-2.0, 0.0, 4.0),
         1.0,
-        Material::new(Color::GREEN, Some(10.0), Some(0.4)),
+        Material::new(Rgb::GREEN, Some(10.0), Some(0.4)),
     ),
     Shape::new_sphere(
         Vec3::new(0.0, -5001.0, 0.0),
         5000.0,
-        Material::new(Color::YELLOW, Some(1000.0), Some(0.5)),
+        Material::new_textured(
+            Texture::Procedural(ProceduralTexture::checker(
+                Rgb::YELLOW,
+                Rgb::new(0.5, 0.5, 0.0),
+                TextureSpace::World,
+                1.0,
+            )),
+            Some(1000.0),
+            Some(0.5),
+        ),
     ),
 ];
 
 const LIGHTS: &[Light] = &[
-    Light::new_ambient(0.2),
-    Light::new_point(0.6, Vec3::new(2.0, 1.0, 0.0)),
-    Light::new_directional(0.2, Vec3::new(1.0, 4.0, 4.0)),
+    Light::new_ambient(Rgb::splat(0.2)),
+    Light::new_point(Rgb::splat(0.6), Vec3::new(2.0, 1.0, 0.0)),
+    Light::new_directional(Rgb::splat(0.2), Vec3::new(1.0, 4.0, 4.0)),
 ];
 
+// off to match the book, e.g. caustics from the reflective spheres with
+// Some(PhotonMapper::new(200_000).with_indirect(false).with_gather(50, 0.2))
+const PHOTON_MAPPING: Option<PhotonMapper> = None;
+
+static PHOTON_MAPS: OnceLock<Arc<PhotonMaps>> = OnceLock::new();
+
+/// The scene lights plus the photon map gather when enabled
+fn lights() -> Vec<Light> {
+    let mut lights = LIGHTS.to_vec();
+    if let Some(mapper) = PHOTON_MAPPING {
+        // the scene doesn't change so the photons are only shot once
+        let maps = PHOTON_MAPS.get_or_init(|| Arc::new(mapper.build(LIGHTS, SPHERES)));
+        lights.push(Light::new_photon_map(maps.clone()));
+    }
+    lights
+}
+
+const BACKGROUND: Environment = Environment::color(Rgb::BLACK);
+
+const MEDIA: Media = Media::new();
+
 const REFLECT_DEPTH: usize = 3;
 
+// 1 spp like the book, chapter 2 shows how to supersample
+const ANTIALIASING: Antialiasing =
+    Antialiasing::new(1, SamplePattern::Regular, ReconstructionFilter::Box);
+
+/// Maps the HDR result to the display, clamped like the book by default
+const TONE_MAPPING: ToneMapping = ToneMapping::Clamp;
+
+/// Display expects sRGB, the book writes linear colors
+const GAMMA_CORRECTION: bool = false;
+
+/// Packs both eyes of a stereo rig into the window when set
+const STEREO: Option<StereoLayout> = None;
+
+fn render_eye(canvas: &Canvas, camera: &Camera) -> Framebuffer {
+    let lights = lights();
+
+    let mut framebuffer = Framebuffer::from_canvas(canvas)
+        .with_tone_mapping(TONE_MAPPING)
+        .with_gamma_correction(GAMMA_CORRECTION);
+
+    // TODO: parallelize this
+    ANTIALIASING.render(&mut framebuffer, |x, y| {
+        let Some((origin, direction)) = camera.get_ray(canvas.to_viewport_subpixel(x, y)) else {
+            return Rgb::BLACK;
+        };
+        trace_ray(
+            origin,
+            direction,
+            1.0,
+            INFINITY,
+            camera.sample_time(),
+            REFLECT_DEPTH,
+            &lights,
+            SPHERES,
+            &BACKGROUND,
+            &MEDIA,
+        )
+    });
+
+    framebuffer
+}
+
 fn render(canvas: &Canvas) -> anyhow::Result<()> {
     let camera = Camera::default();
 
-    // TODO: parallelize this
-    for x in -canvas.get_half_width()..=canvas.get_half_width() {
-        for y in -canvas.get_half_height()..=canvas.get_half_height() {
-            let direction = camera.get_rotation() * canvas.to_viewport(x, y);
-            let color = trace_ray(
-                camera.get_translation().as_dvec3(),
-                direction.as_dvec3(),
-                1.0,
-                INFINITY,
-                REFLECT_DEPTH,
-                LIGHTS,
-                SPHERES,
-                Color::BLACK,
-            );
-            canvas.put_pixel(IVec3::new(x, y, 1), color)?;
+    let framebuffer = match STEREO {
+        Some(layout) => {
+            // converged on the red sphere
+            let rig = StereoRig::new(camera, 0.1, 3.0);
+            let left = render_eye(canvas, &rig.get_eye(Eye::Left));
+            let right = render_eye(canvas, &rig.get_eye(Eye::Right));
+
+            let mut framebuffer = Framebuffer::from_canvas(canvas)
+                .with_tone_mapping(TONE_MAPPING)
+                .with_gamma_correction(GAMMA_CORRECTION);
+            layout.compose(&left, &right, &mut framebuffer);
+            framebuffer
         }
-    }
+        None => render_eye(canvas, &camera),
+    };
+
+    framebuffer.present(canvas)?;
 
     Ok(())
 }
@@ -0,0 +1,276 @@
+use rand::Rng;
+
+use crate::{Framebuffer, Rgb};
+
+/// Where sample positions are placed within a pixel
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SamplePattern {
+    /// Evenly spaced grid of sample centers
+    #[default]
+    Regular,
+
+    /// Independent uniformly random positions
+    Jittered,
+
+    /// Grid of cells with one random position in each cell
+    Stratified,
+
+    /// Low discrepancy Halton sequence (bases 2 and 3)
+    /// with a random per-pixel offset to avoid structured aliasing
+    Halton,
+}
+
+/// Filter used to weight samples when reconstructing a pixel
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ReconstructionFilter {
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+impl ReconstructionFilter {
+    /// Filter support radius in pixels
+    pub fn get_radius(&self) -> f32 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.0,
+        }
+    }
+
+    /// Filter weight for a sample offset from the pixel center
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        let r = self.get_radius();
+        match self {
+            Self::Box => 1.0,
+            Self::Tent => (1.0 - x.abs() / r).max(0.0) * (1.0 - y.abs() / r).max(0.0),
+            Self::Gaussian => {
+                const ALPHA: f32 = 2.0;
+
+                let edge = (-ALPHA * r * r).exp();
+                let g = |v: f32| ((-ALPHA * v * v).exp() - edge).max(0.0);
+                g(x) * g(y)
+            }
+            // Mitchell-Netravali with B = C = 1/3
+            Self::Mitchell => mitchell_1d(2.0 * x / r) * mitchell_1d(2.0 * y / r),
+        }
+    }
+}
+
+fn mitchell_1d(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+
+    let x = x.abs();
+    if x >= 2.0 {
+        0.0
+    } else if x >= 1.0 {
+        ((-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C))
+            / 6.0
+    } else {
+        ((12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B))
+            / 6.0
+    }
+}
+
+/// Radical inverse of i in the given base, used to build the Halton sequence
+fn radical_inverse(mut i: usize, base: usize) -> f32 {
+    let inv_base = 1.0 / base as f32;
+
+    let mut result = 0.0;
+    let mut f = inv_base;
+    while i > 0 {
+        result += (i % base) as f32 * f;
+        i /= base;
+        f *= inv_base;
+    }
+    result
+}
+
+/// Per-pixel supersampling settings
+#[derive(Debug, Copy, Clone)]
+pub struct Antialiasing {
+    samples: usize,
+    pattern: SamplePattern,
+    filter: ReconstructionFilter,
+    adaptive_threshold: Option<f32>,
+}
+
+impl Default for Antialiasing {
+    fn default() -> Self {
+        Self::new(1, SamplePattern::Regular, ReconstructionFilter::Box)
+    }
+}
+
+impl Antialiasing {
+    /// Grid based patterns round the sample count down to a square number
+    pub const fn new(samples: usize, pattern: SamplePattern, filter: ReconstructionFilter) -> Self {
+        Self {
+            samples,
+            pattern,
+            filter,
+            adaptive_threshold: None,
+        }
+    }
+
+    /// Only supersample pixels whose color differs from a neighbour
+    /// by more than the threshold in any channel
+    pub const fn with_adaptive(mut self, threshold: f32) -> Self {
+        self.adaptive_threshold = Some(threshold);
+        self
+    }
+
+    #[inline]
+    pub fn get_samples(&self) -> usize {
+        self.samples
+    }
+
+    #[inline]
+    pub fn get_pattern(&self) -> SamplePattern {
+        self.pattern
+    }
+
+    #[inline]
+    pub fn get_filter(&self) -> ReconstructionFilter {
+        self.filter
+    }
+
+    #[inline]
+    pub fn get_adaptive_threshold(&self) -> Option<f32> {
+        self.adaptive_threshold
+    }
+
+    /// Sample positions in [0, 1)^2
+    pub fn sample_positions(&self, rng: &mut impl Rng) -> Vec<(f32, f32)> {
+        let samples = self.samples.max(1);
+
+        match self.pattern {
+            SamplePattern::Regular | SamplePattern::Stratified => {
+                let n = ((samples as f32).sqrt() as usize).max(1);
+                let inv_n = 1.0 / n as f32;
+                let jitter = self.pattern == SamplePattern::Stratified;
+
+                let mut positions = Vec::with_capacity(n * n);
+                for i in 0..n {
+                    for j in 0..n {
+                        let (dx, dy) = if jitter {
+                            (rng.gen::<f32>(), rng.gen::<f32>())
+                        } else {
+                            (0.5, 0.5)
+                        };
+                        positions.push(((i as f32 + dx) * inv_n, (j as f32 + dy) * inv_n));
+                    }
+                }
+                positions
+            }
+            SamplePattern::Jittered => (0..samples)
+                .map(|_| (rng.gen::<f32>(), rng.gen::<f32>()))
+                .collect(),
+            SamplePattern::Halton => {
+                let (ox, oy) = (rng.gen::<f32>(), rng.gen::<f32>());
+                (0..samples)
+                    .map(|i| {
+                        // skip 0 which is always at the origin
+                        let x = radical_inverse(i + 1, 2) + ox;
+                        let y = radical_inverse(i + 1, 3) + oy;
+                        (x.fract(), y.fract())
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Samples and reconstructs a single pixel
+    ///
+    /// trace is given the canvas space position of each sample
+    pub fn sample_pixel(
+        &self,
+        x: i32,
+        y: i32,
+        rng: &mut impl Rng,
+        trace: &mut impl FnMut(f32, f32) -> Rgb,
+    ) -> Rgb {
+        // spread the samples across the filter support
+        let r = self.filter.get_radius();
+
+        let mut color = Rgb::BLACK;
+        let mut total_weight = 0.0;
+        for (u, v) in self.sample_positions(rng) {
+            let dx = (u * 2.0 - 1.0) * r;
+            let dy = (v * 2.0 - 1.0) * r;
+
+            let weight = self.filter.evaluate(dx, dy);
+            if weight == 0.0 {
+                continue;
+            }
+
+            color += trace(x as f32 + dx, y as f32 + dy) * weight;
+            total_weight += weight;
+        }
+
+        if total_weight.abs() > f32::EPSILON {
+            color / total_weight
+        } else {
+            trace(x as f32, y as f32)
+        }
+    }
+
+    fn needs_refinement(&self, framebuffer: &Framebuffer, x: i32, y: i32, threshold: f32) -> bool {
+        let color = framebuffer.get_pixel(x, y);
+
+        [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx.abs() > framebuffer.get_half_width() || ny.abs() > framebuffer.get_half_height() {
+                return false;
+            }
+
+            let diff = color - framebuffer.get_pixel(nx, ny);
+            diff.map(f32::abs).max_component() > threshold
+        })
+    }
+
+    /// Renders every pixel of the framebuffer
+    ///
+    /// trace is given the canvas space position of each sample
+    pub fn render(&self, framebuffer: &mut Framebuffer, mut trace: impl FnMut(f32, f32) -> Rgb) {
+        let mut rng = rand::thread_rng();
+
+        let half_width = framebuffer.get_half_width();
+        let half_height = framebuffer.get_half_height();
+
+        if let Some(threshold) = self.adaptive_threshold {
+            // first pass is a single sample through the center of each pixel
+            for x in -half_width..=half_width {
+                for y in -half_height..=half_height {
+                    framebuffer.put_pixel(x, y, trace(x as f32, y as f32));
+                }
+            }
+
+            // second pass supersamples anything that stands out from its neighbours
+            let centers = framebuffer.clone();
+            for x in -half_width..=half_width {
+                for y in -half_height..=half_height {
+                    if self.needs_refinement(&centers, x, y, threshold) {
+                        let color = self.sample_pixel(x, y, &mut rng, &mut trace);
+                        framebuffer.put_pixel(x, y, color);
+                    }
+                }
+            }
+        } else {
+            for x in -half_width..=half_width {
+                for y in -half_height..=half_height {
+                    let color = self.sample_pixel(x, y, &mut rng, &mut trace);
+                    framebuffer.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+}
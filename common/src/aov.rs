@@ -0,0 +1,350 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use glam::{DVec3, Vec3};
+
+use crate::canvas::*;
+use crate::color::*;
+use crate::framebuffer::*;
+use crate::pick::*;
+use crate::shapes::*;
+
+/// Arbitrary output variables written alongside the color
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AovPass {
+    /// Ray t of the primary hit
+    Depth,
+
+    /// World space hit point
+    Position,
+
+    /// Shading normal, facing the ray
+    Normal,
+
+    /// Material color after texturing
+    Albedo,
+
+    /// Index of the shape plus one, 0 is the background
+    ObjectId,
+
+    /// Material id plus one, 0 is the background
+    MaterialId,
+}
+
+impl AovPass {
+    pub const ALL: [Self; 6] = [
+        Self::Depth,
+        Self::Position,
+        Self::Normal,
+        Self::Albedo,
+        Self::ObjectId,
+        Self::MaterialId,
+    ];
+
+    /// Layer and channel names used in multi-layer EXR files
+    pub fn get_channels(&self) -> &'static [&'static str] {
+        match self {
+            Self::Depth => &["depth.Z"],
+            Self::Position => &["position.X", "position.Y", "position.Z"],
+            Self::Normal => &["normal.X", "normal.Y", "normal.Z"],
+            Self::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Self::ObjectId => &["object.id"],
+            Self::MaterialId => &["material.id"],
+        }
+    }
+}
+
+/// Surface information of a primary hit
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AovSample {
+    pub depth: f32,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub albedo: Rgb,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        Self::MISS
+    }
+}
+
+impl AovSample {
+    /// Rays that don't hit anything
+    pub const MISS: Self = Self {
+        depth: f32::INFINITY,
+        position: Vec3::ZERO,
+        normal: Vec3::ZERO,
+        albedo: Rgb::BLACK,
+        object_id: 0,
+        material_id: 0,
+    };
+
+    /// Values of the pass in channel order
+    pub fn get_pass(&self, pass: AovPass) -> Vec<f32> {
+        match pass {
+            AovPass::Depth => vec![self.depth],
+            AovPass::Position => self.position.to_array().to_vec(),
+            AovPass::Normal => self.normal.to_array().to_vec(),
+            AovPass::Albedo => vec![self.albedo.r, self.albedo.g, self.albedo.b],
+            AovPass::ObjectId => vec![self.object_id as f32],
+            AovPass::MaterialId => vec![self.material_id as f32],
+        }
+    }
+}
+
+/// Trace the given ray and return the surface information of the intersection
+pub fn trace_aov(
+    origin: DVec3,
+    direction: DVec3,
+    t_min: f64,
+    t_max: f64,
+    time: f32,
+    shapes: impl AsRef<[Shape]>,
+) -> AovSample {
+    query_ray(origin, direction, t_min, t_max, time, shapes).map_or(AovSample::MISS, |hit| {
+        AovSample {
+            depth: hit.t as f32,
+            position: hit.point.as_vec3(),
+            normal: hit.normal.as_vec3(),
+            albedo: hit.material.get_color(),
+            object_id: hit.shape as u32 + 1,
+            material_id: hit.material.get_id() + 1,
+        }
+    })
+}
+
+/// Per-pixel AOV samples, laid out like a Framebuffer
+#[derive(Debug, Clone)]
+pub struct AovBuffer {
+    half_width: i32,
+    half_height: i32,
+
+    samples: Vec<AovSample>,
+}
+
+impl AovBuffer {
+    pub fn new(half_width: i32, half_height: i32) -> Self {
+        let width = (half_width * 2 + 1) as usize;
+        let height = (half_height * 2 + 1) as usize;
+
+        Self {
+            half_width,
+            half_height,
+            samples: vec![AovSample::MISS; width * height],
+        }
+    }
+
+    pub fn from_canvas(canvas: &Canvas) -> Self {
+        Self::new(canvas.get_half_width(), canvas.get_half_height())
+    }
+
+    #[inline]
+    pub fn get_half_width(&self) -> i32 {
+        self.half_width
+    }
+
+    #[inline]
+    pub fn get_half_height(&self) -> i32 {
+        self.half_height
+    }
+
+    #[inline]
+    pub fn get_width(&self) -> usize {
+        (self.half_width * 2 + 1) as usize
+    }
+
+    #[inline]
+    pub fn get_height(&self) -> usize {
+        (self.half_height * 2 + 1) as usize
+    }
+
+    #[inline]
+    fn index(&self, x: i32, y: i32) -> usize {
+        // rows top to bottom, same as the framebuffer
+        let row = (self.half_height - y) as usize;
+        let col = (self.half_width + x) as usize;
+        row * self.get_width() + col
+    }
+
+    #[inline]
+    pub fn get_sample(&self, x: i32, y: i32) -> &AovSample {
+        &self.samples[self.index(x, y)]
+    }
+
+    #[inline]
+    pub fn put_sample(&mut self, x: i32, y: i32, sample: AovSample) {
+        let idx = self.index(x, y);
+        self.samples[idx] = sample;
+    }
+
+    /// Fills the buffer with one unfiltered sample at each pixel center
+    ///
+    /// Filtering would blend ids and depths across edges so none is applied
+    pub fn render(&mut self, mut trace: impl FnMut(f32, f32) -> AovSample) {
+        for x in -self.half_width..=self.half_width {
+            for y in -self.half_height..=self.half_height {
+                let sample = trace(x as f32, y as f32);
+                self.put_sample(x, y, sample);
+            }
+        }
+    }
+
+    /// Writes a single pass as a Portable Float Map (.pfm) image
+    pub fn write_pass(&self, pass: AovPass, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let channels = pass.get_channels().len();
+        let magic = if channels == 1 { "Pf" } else { "PF" };
+
+        // negative scale means little endian
+        write!(
+            writer,
+            "{}\n{} {}\n-1.0\n",
+            magic,
+            self.get_width(),
+            self.get_height()
+        )?;
+
+        // PFM rows go bottom to top
+        for row in self.samples.chunks(self.get_width()).rev() {
+            for sample in row {
+                for value in sample.get_pass(pass) {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes every pass as a separate .pfm image named after the pass
+    pub fn write_passes(&self, directory: impl AsRef<Path>) -> anyhow::Result<()> {
+        let directory = directory.as_ref();
+        for pass in AovPass::ALL {
+            let name = format!("{:?}.pfm", pass).to_lowercase();
+            self.write_pass(pass, directory.join(name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the unresolved color and every pass as layers of an uncompressed OpenEXR image
+    pub fn write_exr(&self, color: &Framebuffer, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            color.get_width() == self.get_width() && color.get_height() == self.get_height(),
+            "framebuffer size doesn't match the AOV buffer"
+        );
+
+        let width = self.get_width();
+        let height = self.get_height();
+
+        // every channel as full rows of floats, EXR wants them sorted by name
+        let mut channels: Vec<(&str, Vec<f32>)> = vec![];
+        for (idx, name) in ["R", "G", "B"].into_iter().enumerate() {
+            let mut values = Vec::with_capacity(width * height);
+            for y in (-self.half_height..=self.half_height).rev() {
+                for x in -self.half_width..=self.half_width {
+                    let pixel = color.get_pixel(x, y);
+                    values.push([pixel.r, pixel.g, pixel.b][idx]);
+                }
+            }
+            channels.push((name, values));
+        }
+        for pass in AovPass::ALL {
+            for (idx, name) in pass.get_channels().iter().enumerate() {
+                let values = self
+                    .samples
+                    .iter()
+                    .map(|sample| sample.get_pass(pass)[idx])
+                    .collect();
+                channels.push((name, values));
+            }
+        }
+        channels.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut header = vec![];
+        header.extend_from_slice(&20000630_i32.to_le_bytes());
+        // version 2, single part scanline
+        header.extend_from_slice(&2_i32.to_le_bytes());
+
+        let mut chlist = vec![];
+        for (name, _) in &channels {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            // FLOAT, not linear, reserved, x / y sampling
+            chlist.extend_from_slice(&2_i32.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1_i32.to_le_bytes());
+            chlist.extend_from_slice(&1_i32.to_le_bytes());
+        }
+        chlist.push(0);
+        write_exr_attribute(&mut header, "channels", "chlist", &chlist);
+
+        write_exr_attribute(&mut header, "compression", "compression", &[0]);
+
+        let mut window = vec![];
+        for v in [0, 0, width as i32 - 1, height as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        write_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+
+        write_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_exr_attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1.0_f32.to_le_bytes(),
+        );
+        write_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_exr_attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1.0_f32.to_le_bytes(),
+        );
+        header.push(0);
+
+        // one scanline per chunk: y, data size, then each channel's row
+        let line_size = channels.len() * width * 4;
+        let chunk_size = 8 + line_size;
+        let table_size = height * 8;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&header)?;
+
+        for y in 0..height {
+            let offset = header.len() + table_size + y * chunk_size;
+            writer.write_all(&(offset as u64).to_le_bytes())?;
+        }
+
+        for y in 0..height {
+            writer.write_all(&(y as i32).to_le_bytes())?;
+            writer.write_all(&(line_size as i32).to_le_bytes())?;
+            for (_, values) in &channels {
+                for value in &values[y * width..(y + 1) * width] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+fn write_exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
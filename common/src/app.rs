@@ -1,14 +1,18 @@
-use sdl2::{event::Event, keyboard::Keycode, pixels::Color, EventPump, Sdl};
+use std::cell::RefCell;
+
+use sdl2::{event::Event, keyboard::Keycode, mouse::MouseButton, pixels::Color, EventPump, Sdl};
 
 use crate::Canvas;
 
-type RenderCb = fn(&Canvas) -> anyhow::Result<()>;
+type RenderCb = Box<dyn FnMut(&Canvas) -> anyhow::Result<()>>;
+type ClickCb = Box<dyn FnMut(&Canvas, i32, i32) -> anyhow::Result<()>>;
 
 pub struct App {
     sdl_context: Sdl,
     canvas: Canvas,
 
-    render: Option<RenderCb>,
+    render: RefCell<Option<RenderCb>>,
+    click: RefCell<Option<ClickCb>>,
 }
 
 impl App {
@@ -16,32 +20,43 @@ impl App {
         AppBuilder::default()
     }
 
-    fn handle_events(&self, event_pump: &mut EventPump) -> bool {
+    fn handle_events(&self, event_pump: &mut EventPump) -> anyhow::Result<bool> {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => return false,
+                } => return Ok(false),
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some(click) = self.click.borrow_mut().as_mut() {
+                        let (x, y) = self.canvas.from_screen(x, y);
+                        click(&self.canvas, x, y)?;
+                    }
+                }
                 _ => {}
             }
         }
 
-        true
+        Ok(true)
     }
 
     #[allow(dead_code)]
     fn run_debug(&self) -> anyhow::Result<()> {
         self.canvas.clear(Color::BLACK);
-        if let Some(render) = self.render {
+        if let Some(render) = self.render.borrow_mut().as_mut() {
             render(&self.canvas)?;
         }
         self.canvas.present();
 
         let mut event_pump = self.sdl_context.event_pump().map_err(anyhow::Error::msg)?;
         'running: loop {
-            if !self.handle_events(&mut event_pump) {
+            if !self.handle_events(&mut event_pump)? {
                 break 'running;
             }
 
@@ -60,12 +75,12 @@ impl App {
             self.canvas.clear(Color::BLACK);
 
             // pump the event loop
-            if !self.handle_events(&mut event_pump) {
+            if !self.handle_events(&mut event_pump)? {
                 break 'running;
             }
 
             // render
-            if let Some(render) = self.render {
+            if let Some(render) = self.render.borrow_mut().as_mut() {
                 render(&self.canvas)?;
             }
 
@@ -86,6 +101,7 @@ pub struct AppBuilder {
     window_height: u32,
 
     render: Option<RenderCb>,
+    click: Option<ClickCb>,
 }
 
 impl Default for AppBuilder {
@@ -95,6 +111,7 @@ impl Default for AppBuilder {
             window_width: 800,
             window_height: 600,
             render: None,
+            click: None,
         }
     }
 }
@@ -113,8 +130,18 @@ impl AppBuilder {
         self
     }
 
-    pub fn render(mut self, render: RenderCb) -> Self {
-        self.render = Some(render);
+    pub fn render(mut self, render: impl FnMut(&Canvas) -> anyhow::Result<()> + 'static) -> Self {
+        self.render = Some(Box::new(render));
+
+        self
+    }
+
+    /// Called with the canvas coordinates of left mouse clicks, for selection tools
+    pub fn on_click(
+        mut self,
+        click: impl FnMut(&Canvas, i32, i32) -> anyhow::Result<()> + 'static,
+    ) -> Self {
+        self.click = Some(Box::new(click));
 
         self
     }
@@ -137,7 +164,8 @@ impl AppBuilder {
         Ok(App {
             sdl_context,
             canvas,
-            render: self.render,
+            render: RefCell::new(self.render),
+            click: RefCell::new(self.click),
         })
     }
 }
@@ -0,0 +1,239 @@
+use std::f64::consts::PI;
+
+use glam::DVec3;
+use rand::Rng;
+
+use crate::color::*;
+use crate::math::*;
+use crate::ray::*;
+use crate::shapes::*;
+
+/// Minimum GGX alpha, perfectly smooth surfaces blow up the distribution
+const MIN_ALPHA: f64 = 1e-3;
+
+/// Reflectance of dielectrics at normal incidence
+const DIELECTRIC_F0: f32 = 0.04;
+
+/// Diffuse lobe used by the metal / roughness model
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DiffuseModel {
+    #[default]
+    Lambert,
+
+    /// Rough diffuse surfaces (clay, plaster) that appear flatter than Lambert
+    OrenNayar,
+}
+
+/// How a material reflects light
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ShadingModel {
+    /// The book's diffuse + Phong specular model
+    #[default]
+    Phong,
+
+    /// Physically based Cook-Torrance GGX specular with an energy conserving diffuse lobe
+    MetalRoughness {
+        metallic: f32,
+        roughness: f32,
+        diffuse: DiffuseModel,
+    },
+}
+
+/// Fresnel reflectance at normal incidence
+fn f0(base_color: Rgb, metallic: f32) -> Rgb {
+    Rgb::splat(DIELECTRIC_F0).lerp(base_color, metallic)
+}
+
+/// Schlick's Fresnel approximation with a colored F0
+fn fresnel_schlick(f0: Rgb, cos_theta: f64) -> Rgb {
+    let f = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5) as f32;
+    f0 + (Rgb::WHITE - f0) * f
+}
+
+/// GGX / Trowbridge-Reitz normal distribution
+fn ggx_d(n_dot_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Smith height-correlated masking-shadowing for GGX
+fn smith_g(n_dot_l: f64, n_dot_v: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let lambda = |c: f64| {
+        let c2 = c * c;
+        (-1.0 + (1.0 + a2 * (1.0 - c2) / c2).sqrt()) * 0.5
+    };
+    1.0 / (1.0 + lambda(n_dot_l) + lambda(n_dot_v))
+}
+
+/// Oren-Nayar (qualitative model) scale on top of Lambert
+fn oren_nayar(n: DVec3, l: DVec3, v: DVec3, sigma: f64) -> f64 {
+    let s2 = sigma * sigma;
+    let a = 1.0 - 0.5 * s2 / (s2 + 0.33);
+    let b = 0.45 * s2 / (s2 + 0.09);
+
+    let n_dot_l = n.dot(l).clamp(-1.0, 1.0);
+    let n_dot_v = n.dot(v).clamp(-1.0, 1.0);
+
+    // azimuthal difference between l and v projected onto the surface
+    let lp = (l - n * n_dot_l).normalize_or_zero();
+    let vp = (v - n * n_dot_v).normalize_or_zero();
+    let cos_phi = lp.dot(vp).max(0.0);
+
+    let theta_i = n_dot_l.acos();
+    let theta_o = n_dot_v.acos();
+    let alpha = theta_i.max(theta_o);
+    let beta = theta_i.min(theta_o);
+
+    a + b * cos_phi * alpha.sin() * beta.tan()
+}
+
+/// Evaluates the metal / roughness BRDF times the cosine term for unit vectors
+///
+/// l points towards the light and v points towards the viewer
+pub fn evaluate_brdf(
+    base_color: Rgb,
+    metallic: f32,
+    roughness: f32,
+    diffuse: DiffuseModel,
+    n: DVec3,
+    l: DVec3,
+    v: DVec3,
+) -> Rgb {
+    let n_dot_l = n.dot(l);
+    let n_dot_v = n.dot(v);
+    if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+        return Rgb::BLACK;
+    }
+
+    let alpha = (roughness as f64 * roughness as f64).max(MIN_ALPHA);
+
+    let h = (l + v).normalize();
+    let n_dot_h = n.dot(h).max(0.0);
+    let v_dot_h = v.dot(h).max(0.0);
+
+    let f = fresnel_schlick(f0(base_color, metallic), v_dot_h);
+    let d = ggx_d(n_dot_h, alpha);
+    let g = smith_g(n_dot_l, n_dot_v, alpha);
+    let specular = f * (d * g / (4.0 * n_dot_l * n_dot_v)) as f32;
+
+    // whatever isn't reflected by the specular lobe is available for diffuse,
+    // and metals have no diffuse at all
+    let kd = (Rgb::WHITE - f) * (1.0 - metallic);
+    let lambert = match diffuse {
+        DiffuseModel::Lambert => 1.0,
+        DiffuseModel::OrenNayar => oren_nayar(n, l, v, roughness as f64),
+    };
+    let diffuse = kd * base_color * (lambert / PI) as f32;
+
+    (diffuse + specular) * n_dot_l as f32
+}
+
+/// Importance samples the metal / roughness BRDF
+///
+/// Returns the sampled direction towards the light and its weight (brdf * cos / pdf)
+pub fn sample_brdf(
+    base_color: Rgb,
+    metallic: f32,
+    roughness: f32,
+    diffuse: DiffuseModel,
+    n: DVec3,
+    v: DVec3,
+    rng: &mut impl Rng,
+) -> Option<(DVec3, Rgb)> {
+    let alpha = (roughness as f64 * roughness as f64).max(MIN_ALPHA);
+
+    // metals only have a specular lobe, dielectrics split evenly
+    let specular_probability = 0.5 + 0.5 * metallic as f64;
+
+    let l = if rng.gen::<f64>() < specular_probability {
+        // sample a GGX microfacet normal and reflect around it
+        let u: f64 = rng.gen();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let tan2_theta = alpha * alpha * u / (1.0 - u);
+        let cos_theta = 1.0 / (1.0 + tan2_theta).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+        let (b1, b2) = orthonormal_basis(n);
+        let h = sin_theta * phi.cos() * b1 + sin_theta * phi.sin() * b2 + cos_theta * n;
+        reflect_ray(v, h)
+    } else {
+        random_cosine_hemisphere(n, rng)
+    };
+
+    let n_dot_l = n.dot(l);
+    if n_dot_l <= 0.0 {
+        return None;
+    }
+
+    let h = (l + v).normalize();
+    let n_dot_h = n.dot(h).max(0.0);
+    let v_dot_h = v.dot(h).max(1e-6);
+
+    let specular_pdf = ggx_d(n_dot_h, alpha) * n_dot_h / (4.0 * v_dot_h);
+    let diffuse_pdf = n_dot_l / PI;
+    let pdf = specular_probability * specular_pdf + (1.0 - specular_probability) * diffuse_pdf;
+    if pdf <= 0.0 {
+        return None;
+    }
+
+    let f = evaluate_brdf(base_color, metallic, roughness, diffuse, n, l, v);
+    Some((l, f / pdf as f32))
+}
+
+/// Reflected light for a material lit by a light of the given intensity
+/// from direction l (towards the light) seen from v (towards the viewer)
+///
+/// Neither l nor v need to be normalized.
+/// Intensities follow the book's convention where a light of intensity 1
+/// shining straight down on a white diffuse surface reflects 1
+pub fn shade(material: &Material, intensity: Rgb, normal: DVec3, l: DVec3, v: DVec3) -> Rgb {
+    match material.get_shading() {
+        ShadingModel::Phong => {
+            // diffuse
+            let d = normal.dot(l);
+            let diffuse = if d > 0.0 {
+                d / (normal.length() * l.length())
+            } else {
+                0.0
+            };
+
+            // specular
+            let specular = if let Some(shininess) = material.get_shininess() {
+                let r = reflect_ray(l, normal);
+
+                let d = r.dot(v);
+                if d > 0.0 {
+                    (d / (r.length() * v.length())).powf(shininess as f64)
+                } else {
+                    0.0
+                }
+            } else {
+                0.0
+            };
+
+            material.get_color() * intensity * (diffuse + specular) as f32
+        }
+        ShadingModel::MetalRoughness {
+            metallic,
+            roughness,
+            diffuse,
+        } => {
+            let l = l.normalize_or_zero();
+            let v = v.normalize_or_zero();
+
+            // scale by pi so a white lambertian surface matches the Phong diffuse
+            let brdf = evaluate_brdf(
+                material.get_color(),
+                metallic,
+                roughness,
+                diffuse,
+                normal,
+                l,
+                v,
+            );
+            intensity * brdf * PI as f32
+        }
+    }
+}
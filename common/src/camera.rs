@@ -1,9 +1,60 @@
-use glam::{Mat4, Quat, Vec3};
+use glam::{DVec3, Mat4, Quat, Vec3};
+use rand::Rng;
+
+use crate::math::*;
+use crate::projection::*;
+
+/// Shape of the lens aperture, which is the shape out of focus highlights take
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Bokeh {
+    #[default]
+    Circle,
+
+    /// Regular polygon formed by the given number of aperture blades
+    Polygon { blades: u32, rotation: f32 },
+}
+
+impl Bokeh {
+    /// Uniformly samples a point in the unit sized aperture
+    pub fn sample(&self, rng: &mut impl Rng) -> (f64, f64) {
+        match *self {
+            Self::Circle => random_in_unit_disk(rng),
+            Self::Polygon { blades, rotation } => {
+                let blades = blades.max(3);
+                let step = std::f64::consts::TAU / blades as f64;
+
+                // pick one of the triangles fanning out from the center
+                let i = rng.gen_range(0..blades) as f64;
+                let a0 = rotation as f64 + i * step;
+                let a1 = a0 + step;
+
+                let (mut u, mut v) = (rng.gen::<f64>(), rng.gen::<f64>());
+                if u + v > 1.0 {
+                    u = 1.0 - u;
+                    v = 1.0 - v;
+                }
+
+                (u * a0.cos() + v * a1.cos(), u * a0.sin() + v * a1.sin())
+            }
+        }
+    }
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct Camera {
     translation: Vec3,
     rotation: Quat,
+    projection: CameraProjection,
+
+    aperture: f32,
+    focal_distance: f32,
+    bokeh: Bokeh,
+
+    shutter_open: f32,
+    shutter_close: f32,
+
+    eye_offset: f32,
+    convergence: f32,
 }
 
 impl Camera {
@@ -11,16 +62,53 @@ impl Camera {
         Self {
             translation,
             rotation,
+            ..Default::default()
         }
     }
 
     pub fn from_translation(translation: Vec3) -> Self {
         Self {
             translation,
-            rotation: Quat::default(),
+            ..Default::default()
         }
     }
 
+    pub fn with_projection(mut self, projection: impl Into<CameraProjection>) -> Self {
+        self.projection = projection.into();
+        self
+    }
+
+    /// Turns the pinhole into a thin lens of the given diameter
+    /// focused at the given distance along the view axis
+    pub fn with_lens(mut self, aperture: f32, focal_distance: f32) -> Self {
+        self.aperture = aperture;
+        self.focal_distance = focal_distance;
+        self
+    }
+
+    pub fn with_bokeh(mut self, bokeh: Bokeh) -> Self {
+        self.bokeh = bokeh;
+        self
+    }
+
+    /// Times the shutter is open between, for motion blur of moving spheres in the raytracer
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    /// Moves the eye sideways by the given offset along the camera x axis
+    /// and shears the frustum (off-axis) so it still converges with the
+    /// unshifted view at the given distance, used by stereo rigs
+    ///
+    /// A convergence of 0 keeps the views parallel
+    pub fn with_eye(mut self, offset: f32, convergence: f32) -> Self {
+        self.eye_offset = offset;
+        self.convergence = convergence;
+        self
+    }
+
     #[inline]
     pub fn get_translation(&self) -> Vec3 {
         self.translation
@@ -31,11 +119,137 @@ impl Camera {
         self.rotation
     }
 
+    #[inline]
+    pub fn get_projection(&self) -> CameraProjection {
+        self.projection
+    }
+
+    #[inline]
+    pub fn get_aperture(&self) -> f32 {
+        self.aperture
+    }
+
+    #[inline]
+    pub fn get_focal_distance(&self) -> f32 {
+        self.focal_distance
+    }
+
+    #[inline]
+    pub fn get_bokeh(&self) -> Bokeh {
+        self.bokeh
+    }
+
+    #[inline]
+    pub fn get_shutter_open(&self) -> f32 {
+        self.shutter_open
+    }
+
+    #[inline]
+    pub fn get_shutter_close(&self) -> f32 {
+        self.shutter_close
+    }
+
+    #[inline]
+    pub fn get_eye_offset(&self) -> f32 {
+        self.eye_offset
+    }
+
+    #[inline]
+    pub fn get_convergence(&self) -> f32 {
+        self.convergence
+    }
+
+    /// Sideways shift per unit of depth that keeps the eye converged
+    #[inline]
+    fn get_eye_shear(&self) -> f32 {
+        if self.convergence > 0.0 {
+            self.eye_offset / self.convergence
+        } else {
+            0.0
+        }
+    }
+
+    /// Random time while the shutter is open
+    pub fn sample_time(&self) -> f32 {
+        if self.shutter_close <= self.shutter_open {
+            return self.shutter_open;
+        }
+
+        rand::thread_rng().gen_range(self.shutter_open..self.shutter_close)
+    }
+
     #[inline]
     pub fn get_matrix(&self) -> Mat4 {
         // TODO: this is not right?
         // should be: var cameraMatrix = MultiplyMM4(Transposed(camera.orientation), MakeTranslationMatrix(Multiply(-1, camera.position)));
         // not sure how I'm supposed to transpose the rotation? or if it's even necessary here?
-        Mat4::from_rotation_translation(self.rotation, -1.0 * self.translation)
+        let m = Mat4::from_rotation_translation(self.rotation, -1.0 * self.translation);
+        if self.eye_offset == 0.0 {
+            return m;
+        }
+
+        // camera space to eye space: x' = x - offset + shear * z
+        let mut eye = Mat4::from_translation(Vec3::new(-self.eye_offset, 0.0, 0.0));
+        eye.z_axis.x = self.get_eye_shear();
+        eye * m
+    }
+
+    /// Eye space ray to camera space, the inverse of the shear in get_matrix
+    fn eye_to_camera(&self, origin: DVec3, direction: DVec3) -> (DVec3, DVec3) {
+        if self.eye_offset == 0.0 {
+            return (origin, direction);
+        }
+
+        let offset = self.eye_offset as f64;
+        let shear = self.get_eye_shear() as f64;
+        (
+            DVec3::new(origin.x + offset - shear * origin.z, origin.y, origin.z),
+            DVec3::new(direction.x - shear * direction.z, direction.y, direction.z),
+        )
+    }
+
+    /// World space primary ray (origin, direction) through the given viewport point
+    ///
+    /// The direction keeps the viewport depth so t = 1 is still on the viewport,
+    /// with a lens the origin is jittered across the aperture
+    /// and every ray converges on the focal plane
+    ///
+    /// None if the point is outside of the projected image
+    pub fn get_ray(&self, viewport: Vec3) -> Option<(DVec3, DVec3)> {
+        let (origin, direction) = self.projection.get_ray(viewport.truncate(), viewport.z)?;
+        let (origin, direction) = (origin.as_dvec3(), direction.as_dvec3());
+
+        if self.aperture <= 0.0 || self.focal_distance <= 0.0 || direction.z <= 0.0 {
+            return self.get_center_ray(viewport);
+        }
+
+        let rotation = self.rotation.as_f64();
+        let translation = self.translation.as_dvec3();
+
+        let mut rng = rand::thread_rng();
+
+        let focus = origin + direction * (self.focal_distance as f64 / direction.z);
+
+        let (x, y) = self.bokeh.sample(&mut rng);
+        let lens = origin + DVec3::new(x, y, 0.0) * (self.aperture as f64 * 0.5);
+
+        let direction = (focus - lens) * (direction.z / self.focal_distance as f64);
+
+        let (lens, direction) = self.eye_to_camera(lens, direction);
+        Some((translation + rotation * lens, rotation * direction))
+    }
+
+    /// World space ray through the given viewport point from the center of the lens
+    ///
+    /// Unlike get_ray this is deterministic, for picking and other queries
+    pub fn get_center_ray(&self, viewport: Vec3) -> Option<(DVec3, DVec3)> {
+        let (origin, direction) = self.projection.get_ray(viewport.truncate(), viewport.z)?;
+        let (origin, direction) = self.eye_to_camera(origin.as_dvec3(), direction.as_dvec3());
+
+        let rotation = self.rotation.as_f64();
+        Some((
+            self.translation.as_dvec3() + rotation * origin,
+            rotation * direction,
+        ))
     }
 }
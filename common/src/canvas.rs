@@ -1,9 +1,16 @@
 use std::cell::RefCell;
 
 use glam::{IVec3, Vec3};
-use sdl2::{pixels::Color, render::Canvas as SDLCanvas, video::Window};
-
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    render::Canvas as SDLCanvas,
+    video::Window,
+};
+
+use crate::color::*;
+use crate::framebuffer::*;
 use crate::math::*;
+use crate::projection::*;
 use crate::Triangle;
 
 pub struct Canvas {
@@ -52,6 +59,12 @@ impl Canvas {
         self.half_height
     }
 
+    /// Converts SDL window coordinates (top-left origin, y down) to the book's centered canvas coordinates
+    #[inline]
+    pub fn from_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        (x - self.half_width, self.half_height - y)
+    }
+
     #[inline]
     pub fn to_viewport(&self, x: i32, y: i32) -> Vec3 {
         Vec3::new(
@@ -61,6 +74,16 @@ impl Canvas {
         )
     }
 
+    /// Sub-pixel version of to_viewport for supersampling
+    #[inline]
+    pub fn to_viewport_subpixel(&self, x: f32, y: f32) -> Vec3 {
+        Vec3::new(
+            x * self.width_ratio,
+            y * self.height_ratio,
+            self.viewport_distance,
+        )
+    }
+
     #[inline]
     pub fn from_viewport(&self, x: f32, y: f32) -> Vec3 {
         Vec3::new(
@@ -78,6 +101,16 @@ impl Canvas {
         )
     }
 
+    /// Projects with the given projection, None if it isn't linear and can't be rasterized
+    pub fn project_with(&self, projection: &impl Projection, v: &Vec3) -> Option<Vec3> {
+        if !projection.is_linear() {
+            return None;
+        }
+
+        let p = projection.project(*v, self.viewport_distance)?;
+        Some(self.from_viewport(p.x, p.y))
+    }
+
     pub fn clear(&self, color: Color) {
         self.canvas.borrow_mut().set_draw_color(color);
         self.canvas.borrow_mut().clear();
@@ -288,6 +321,27 @@ impl Canvas {
         Ok(())
     }
 
+    /// Reads back what has been drawn so far, as display (not linear) colors
+    ///
+    /// Lets rasterized images go through the same passes as framebuffers
+    pub fn capture(&self) -> anyhow::Result<Framebuffer> {
+        let canvas = self.canvas.borrow();
+        let (width, _) = canvas.output_size().map_err(anyhow::Error::msg)?;
+        let pixels = canvas
+            .read_pixels(None, PixelFormatEnum::RGB24)
+            .map_err(anyhow::Error::msg)?;
+
+        let mut framebuffer = Framebuffer::new(self.half_width, self.half_height);
+        for (idx, rgb) in pixels.chunks_exact(3).enumerate() {
+            let (x, y) = self.from_screen((idx as u32 % width) as i32, (idx as u32 / width) as i32);
+            if x.abs() <= self.half_width && y.abs() <= self.half_height {
+                framebuffer.put_pixel(x, y, Rgb::from_rgb8(rgb[0], rgb[1], rgb[2]));
+            }
+        }
+
+        Ok(framebuffer)
+    }
+
     pub fn present(&self) {
         self.canvas.borrow_mut().present();
     }
@@ -0,0 +1,173 @@
+use sdl2::pixels::Color;
+
+/// Linear floating-point RGB color
+///
+/// Values are nominally in the range [0, 1]
+/// but are allowed to go above 1.0 while lighting is accumulated.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Rgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Rgb {
+    pub const BLACK: Self = Self::splat(0.0);
+    pub const WHITE: Self = Self::splat(1.0);
+    pub const RED: Self = Self::new(1.0, 0.0, 0.0);
+    pub const GREEN: Self = Self::new(0.0, 1.0, 0.0);
+    pub const BLUE: Self = Self::new(0.0, 0.0, 1.0);
+    pub const YELLOW: Self = Self::new(1.0, 1.0, 0.0);
+    pub const CYAN: Self = Self::new(0.0, 1.0, 1.0);
+    pub const MAGENTA: Self = Self::new(1.0, 0.0, 1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const fn splat(v: f32) -> Self {
+        Self::new(v, v, v)
+    }
+
+    /// Converts 8-bit color channels to [0, 1]
+    pub fn from_rgb8(r: u8, g: u8, b: u8) -> Self {
+        Self::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    }
+
+    #[inline]
+    pub fn max_component(&self) -> f32 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    /// Relative luminance (Rec. 709)
+    #[inline]
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    #[inline]
+    pub fn is_black(&self) -> bool {
+        self.max_component() <= 0.0
+    }
+
+    #[inline]
+    pub fn lerp(&self, other: Self, t: f32) -> Self {
+        *self * (1.0 - t) + other * t
+    }
+
+    #[inline]
+    pub fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+        Self::new(f(self.r), f(self.g), f(self.b))
+    }
+
+    /// Encodes linear color with the sRGB transfer function
+    pub fn to_srgb(&self) -> Self {
+        self.map(|c| {
+            let c = c.clamp(0.0, 1.0);
+            if c <= 0.003_130_8 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        })
+    }
+
+    /// Clamps each channel to [0, 1] and converts it to 8-bits
+    pub fn to_color(&self) -> Color {
+        let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color::RGB(to_u8(self.r), to_u8(self.g), to_u8(self.b))
+    }
+}
+
+impl From<Color> for Rgb {
+    fn from(color: Color) -> Self {
+        Self::from_rgb8(color.r, color.g, color.b)
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(color: Rgb) -> Self {
+        color.to_color()
+    }
+}
+
+impl std::ops::Add for Rgb {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl std::ops::AddAssign for Rgb {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl std::ops::Sub for Rgb {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+impl std::ops::Mul for Rgb {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl std::ops::MulAssign for Rgb {
+    #[inline]
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl std::ops::Mul<f32> for Rgb {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl std::ops::Mul<Rgb> for f32 {
+    type Output = Rgb;
+
+    #[inline]
+    fn mul(self, rhs: Rgb) -> Rgb {
+        rhs * self
+    }
+}
+
+impl std::ops::MulAssign<f32> for Rgb {
+    #[inline]
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = *self * rhs;
+    }
+}
+
+impl std::ops::Div<f32> for Rgb {
+    type Output = Self;
+
+    #[inline]
+    fn div(self, rhs: f32) -> Self {
+        Self::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
+
+impl std::iter::Sum for Rgb {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::BLACK, |acc, c| acc + c)
+    }
+}
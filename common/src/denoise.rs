@@ -0,0 +1,187 @@
+use glam::Vec3;
+
+use crate::aov::*;
+use crate::color::*;
+use crate::framebuffer::*;
+
+/// B3 spline weights of the à-trous kernel
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo below this isn't divided out of the color
+const ALBEDO_EPSILON: f32 = 1e-3;
+
+/// The kernel spans 2^16 pixels by then, more would only overflow the step
+const MAX_ITERATIONS: usize = 16;
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010)
+///
+/// Blurs noise with a widening kernel while the AOV normals, positions and albedo
+/// stop it from bleeding across edges. The color is divided by the albedo first
+/// so texture detail isn't blurred along with the noise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Denoiser {
+    iterations: usize,
+    sigma_color: f32,
+    sigma_normal: f32,
+    sigma_position: f32,
+    sigma_albedo: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+impl Denoiser {
+    /// Each iteration doubles the kernel footprint, up to 16 iterations
+    pub const fn new(iterations: usize) -> Self {
+        Self {
+            iterations: if iterations > MAX_ITERATIONS {
+                MAX_ITERATIONS
+            } else {
+                iterations
+            },
+            sigma_color: 0.5,
+            sigma_normal: 0.1,
+            sigma_position: 0.2,
+            sigma_albedo: 0.1,
+        }
+    }
+
+    /// How different colors, normals, positions and albedo can be and still blend
+    pub const fn with_sigmas(
+        mut self,
+        color: f32,
+        normal: f32,
+        position: f32,
+        albedo: f32,
+    ) -> Self {
+        self.sigma_color = color;
+        self.sigma_normal = normal;
+        self.sigma_position = position;
+        self.sigma_albedo = albedo;
+        self
+    }
+
+    #[inline]
+    pub fn get_iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Denoises the unresolved framebuffer using the AOVs rendered from the same view
+    pub fn apply(&self, framebuffer: &mut Framebuffer, guide: &AovBuffer) {
+        assert!(
+            framebuffer.get_half_width() == guide.get_half_width()
+                && framebuffer.get_half_height() == guide.get_half_height()
+        );
+
+        let half_width = framebuffer.get_half_width();
+        let half_height = framebuffer.get_half_height();
+
+        // filter the lighting only, background pixels are left alone
+        let mut illumination = framebuffer.clone();
+        for x in -half_width..=half_width {
+            for y in -half_height..=half_height {
+                let sample = guide.get_sample(x, y);
+                if sample.object_id != 0 {
+                    let color = framebuffer.get_pixel(x, y);
+                    illumination.put_pixel(x, y, demodulate(color, sample.albedo));
+                }
+            }
+        }
+
+        let mut output = illumination.clone();
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+
+            // finer detail is smoothed less as the kernel grows
+            let sigma_color = self.sigma_color / (1 << iteration) as f32;
+
+            for x in -half_width..=half_width {
+                for y in -half_height..=half_height {
+                    let center = guide.get_sample(x, y);
+                    if center.object_id == 0 {
+                        continue;
+                    }
+
+                    let color = illumination.get_pixel(x, y);
+
+                    let mut sum = Rgb::BLACK;
+                    let mut total_weight = 0.0;
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        for (j, ky) in KERNEL.iter().enumerate() {
+                            let qx = x + (i as i32 - 2) * step;
+                            let qy = y + (j as i32 - 2) * step;
+                            if qx.abs() > half_width || qy.abs() > half_height {
+                                continue;
+                            }
+
+                            let sample = guide.get_sample(qx, qy);
+                            if sample.object_id == 0 {
+                                continue;
+                            }
+
+                            let q_color = illumination.get_pixel(qx, qy);
+                            let weight = kx
+                                * ky
+                                * edge_weight(distance2(color, q_color), sigma_color)
+                                * edge_weight(
+                                    (center.normal - sample.normal).length_squared(),
+                                    self.sigma_normal,
+                                )
+                                * edge_weight(
+                                    (center.position - sample.position).length_squared(),
+                                    self.sigma_position,
+                                )
+                                * edge_weight(
+                                    distance2(center.albedo, sample.albedo),
+                                    self.sigma_albedo,
+                                );
+
+                            sum += q_color * weight;
+                            total_weight += weight;
+                        }
+                    }
+
+                    if total_weight > 0.0 {
+                        output.put_pixel(x, y, sum / total_weight);
+                    }
+                }
+            }
+
+            std::mem::swap(&mut illumination, &mut output);
+        }
+
+        for x in -half_width..=half_width {
+            for y in -half_height..=half_height {
+                let sample = guide.get_sample(x, y);
+                if sample.object_id != 0 {
+                    let color = illumination.get_pixel(x, y);
+                    framebuffer.put_pixel(x, y, remodulate(color, sample.albedo));
+                }
+            }
+        }
+    }
+}
+
+#[inline]
+fn edge_weight(distance2: f32, sigma: f32) -> f32 {
+    (-distance2 / (sigma * sigma).max(f32::EPSILON)).exp()
+}
+
+#[inline]
+fn distance2(a: Rgb, b: Rgb) -> f32 {
+    let d = Vec3::new(a.r - b.r, a.g - b.g, a.b - b.b);
+    d.length_squared()
+}
+
+#[inline]
+fn demodulate(color: Rgb, albedo: Rgb) -> Rgb {
+    color * albedo.map(|a| if a > ALBEDO_EPSILON { 1.0 / a } else { 1.0 })
+}
+
+#[inline]
+fn remodulate(color: Rgb, albedo: Rgb) -> Rgb {
+    color * albedo.map(|a| if a > ALBEDO_EPSILON { a } else { 1.0 })
+}
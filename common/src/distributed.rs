@@ -0,0 +1,1150 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use glam::{Quat, Vec3};
+use rand::Rng;
+
+use crate::brdf::*;
+use crate::camera::*;
+use crate::color::*;
+use crate::environment::*;
+use crate::framebuffer::*;
+use crate::lights::*;
+use crate::math::*;
+use crate::media::*;
+use crate::projection::*;
+use crate::ray::*;
+use crate::shapes::*;
+use crate::spectral::*;
+
+/// Largest half width or height a job can have on the wire, enough for an 8K image
+const MAX_HALF_SIZE: i32 = 4096;
+
+/// Everything a worker needs to trace tiles of an image
+///
+/// Only what can be sent over the wire is supported: static spheres with untextured materials,
+/// ambient, point and directional lights, a solid or gradient background and a pinhole camera
+/// with the default perspective projection, no lens, no shutter and no eye offset
+#[derive(Debug, Clone)]
+pub struct RenderJob {
+    pub half_width: i32,
+    pub half_height: i32,
+    pub camera: Camera,
+    pub shapes: Vec<Shape>,
+    pub lights: Vec<Light>,
+    pub background: Environment,
+    pub reflection_depth: usize,
+
+    /// Jittered samples per pixel, 1 traces through the pixel center
+    pub samples: usize,
+}
+
+impl RenderJob {
+    /// Same mapping as a canvas of the same size
+    #[inline]
+    fn to_viewport(&self, x: f32, y: f32) -> Vec3 {
+        Vec3::new(
+            x / (self.half_width * 2) as f32,
+            y / (self.half_height * 2) as f32,
+            1.0,
+        )
+    }
+
+    /// Traces every pixel of the tile, in rows top to bottom
+    pub fn render_tile(&self, tile: &Tile) -> Vec<Rgb> {
+        let mut rng = rand::thread_rng();
+        let samples = self.samples.max(1);
+        let media = Media::new();
+
+        let mut pixels = Vec::with_capacity(tile.get_pixel_count());
+        for y in (tile.y..tile.y + tile.height).rev() {
+            for x in tile.x..tile.x + tile.width {
+                let mut color = Rgb::BLACK;
+                for _ in 0..samples {
+                    let (dx, dy) = if samples == 1 {
+                        (0.0, 0.0)
+                    } else {
+                        (rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5)
+                    };
+
+                    let viewport = self.to_viewport(x as f32 + dx, y as f32 + dy);
+                    if let Some((origin, direction)) = self.camera.get_ray(viewport) {
+                        color += trace_ray(
+                            origin,
+                            direction,
+                            1.0,
+                            INFINITY,
+                            self.camera.sample_time(),
+                            self.reflection_depth,
+                            &self.lights,
+                            &self.shapes,
+                            &self.background,
+                            &media,
+                        );
+                    }
+                }
+                pixels.push(color / samples as f32);
+            }
+        }
+
+        pixels
+    }
+
+    /// Whether every pixel of the tile is inside of the image
+    fn contains_tile(&self, tile: &Tile) -> bool {
+        let (x, y) = (tile.x as i64, tile.y as i64);
+        let (half_width, half_height) = (self.half_width as i64, self.half_height as i64);
+
+        x >= -half_width
+            && y >= -half_height
+            && x + tile.width as i64 - 1 <= half_width
+            && y + tile.height as i64 - 1 <= half_height
+    }
+
+    fn write(&self, w: &mut impl Write) -> anyhow::Result<()> {
+        // only the camera transform goes over the wire
+        let camera = &self.camera;
+        anyhow::ensure!(
+            camera.get_projection() == CameraProjection::Perspective,
+            "only perspective cameras can be sent to workers"
+        );
+        anyhow::ensure!(
+            camera.get_aperture() == 0.0 && camera.get_bokeh() == Bokeh::default(),
+            "cameras with a lens can't be sent to workers"
+        );
+        anyhow::ensure!(
+            camera.get_shutter_open() == 0.0 && camera.get_shutter_close() == 0.0,
+            "cameras with a shutter can't be sent to workers"
+        );
+        anyhow::ensure!(
+            camera.get_eye_offset() == 0.0,
+            "stereo eye cameras can't be sent to workers"
+        );
+        check_size(self.half_width, self.half_height)?;
+
+        write_i32(w, self.half_width)?;
+        write_i32(w, self.half_height)?;
+
+        write_vec3(w, self.camera.get_translation())?;
+        let rotation = self.camera.get_rotation();
+        write_vec3(w, Vec3::new(rotation.x, rotation.y, rotation.z))?;
+        write_f32(w, rotation.w)?;
+
+        write_u32(w, self.shapes.len() as u32)?;
+        for shape in &self.shapes {
+            write_shape(w, shape)?;
+        }
+
+        write_u32(w, self.lights.len() as u32)?;
+        for light in &self.lights {
+            write_light(w, light)?;
+        }
+
+        write_environment(w, &self.background)?;
+        write_u32(w, self.reflection_depth as u32)?;
+        write_u32(w, self.samples as u32)?;
+
+        Ok(())
+    }
+
+    fn read(r: &mut impl Read) -> anyhow::Result<Self> {
+        let half_width = read_i32(r)?;
+        let half_height = read_i32(r)?;
+        check_size(half_width, half_height)?;
+
+        let translation = read_vec3(r)?;
+        let xyz = read_vec3(r)?;
+        let w = read_f32(r)?;
+        let camera = Camera::new(translation, Quat::from_xyzw(xyz.x, xyz.y, xyz.z, w));
+
+        let shapes = (0..read_u32(r)?)
+            .map(|_| read_shape(r))
+            .collect::<anyhow::Result<_>>()?;
+        let lights = (0..read_u32(r)?)
+            .map(|_| read_light(r))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            half_width,
+            half_height,
+            camera,
+            shapes,
+            lights,
+            background: read_environment(r)?,
+            reflection_depth: read_u32(r)? as usize,
+            samples: read_u32(r)? as usize,
+        })
+    }
+}
+
+/// Rectangle of canvas pixels, x and y are the bottom left corner
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub id: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Tile {
+    /// Zero for tiles with a negative size
+    #[inline]
+    pub fn get_pixel_count(&self) -> usize {
+        self.width.max(0) as usize * self.height.max(0) as usize
+    }
+}
+
+/// Splits the canvas into tiles of at most size x size pixels
+pub fn split_tiles(half_width: i32, half_height: i32, size: i32) -> Vec<Tile> {
+    let size = size.max(1);
+
+    let mut tiles = vec![];
+    for y in (-half_height..=half_height).step_by(size as usize) {
+        for x in (-half_width..=half_width).step_by(size as usize) {
+            tiles.push(Tile {
+                id: tiles.len(),
+                x,
+                y,
+                width: size.min(half_width - x + 1),
+                height: size.min(half_height - y + 1),
+            });
+        }
+    }
+
+    tiles
+}
+
+enum Message {
+    Job(Box<RenderJob>),
+    Tile(Tile),
+    Pixels(usize, Vec<Rgb>),
+    Done,
+}
+
+fn check_size(half_width: i32, half_height: i32) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        (1..=MAX_HALF_SIZE).contains(&half_width) && (1..=MAX_HALF_SIZE).contains(&half_height),
+        "image half size {}x{} is outside of 1..={}",
+        half_width,
+        half_height,
+        MAX_HALF_SIZE
+    );
+    Ok(())
+}
+
+impl Message {
+    fn write(&self, w: &mut impl Write) -> anyhow::Result<()> {
+        match self {
+            Self::Job(job) => {
+                write_u8(w, 0)?;
+                job.write(w)?;
+            }
+            Self::Tile(tile) => {
+                write_u8(w, 1)?;
+                write_u32(w, tile.id as u32)?;
+                write_i32(w, tile.x)?;
+                write_i32(w, tile.y)?;
+                write_i32(w, tile.width)?;
+                write_i32(w, tile.height)?;
+            }
+            Self::Pixels(id, pixels) => {
+                write_u8(w, 2)?;
+                write_u32(w, *id as u32)?;
+                write_u32(w, pixels.len() as u32)?;
+                for pixel in pixels {
+                    write_rgb(w, *pixel)?;
+                }
+            }
+            Self::Done => write_u8(w, 3)?,
+        }
+
+        Ok(())
+    }
+
+    fn read(r: &mut impl Read) -> anyhow::Result<Self> {
+        Ok(match read_u8(r)? {
+            0 => Self::Job(Box::new(RenderJob::read(r)?)),
+            1 => {
+                let tile = Tile {
+                    id: read_u32(r)? as usize,
+                    x: read_i32(r)?,
+                    y: read_i32(r)?,
+                    width: read_i32(r)?,
+                    height: read_i32(r)?,
+                };
+                anyhow::ensure!(
+                    tile.width > 0 && tile.height > 0,
+                    "tile {} has an empty size of {}x{}",
+                    tile.id,
+                    tile.width,
+                    tile.height
+                );
+                Self::Tile(tile)
+            }
+            2 => {
+                let id = read_u32(r)? as usize;
+                let pixels = (0..read_u32(r)?)
+                    .map(|_| read_rgb(r))
+                    .collect::<io::Result<_>>()?;
+                Self::Pixels(id, pixels)
+            }
+            3 => Self::Done,
+            tag => anyhow::bail!("unknown message {}", tag),
+        })
+    }
+
+    /// Writes the message and flushes it out to the peer
+    fn send(&self, w: &mut impl Write) -> anyhow::Result<()> {
+        self.write(w)?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+/// Tiles waiting to be handed out, with how many times each has failed
+#[derive(Default)]
+struct TileQueue {
+    pending: VecDeque<(Tile, usize)>,
+    done: bool,
+}
+
+enum NextTile {
+    Tile(Tile, usize),
+    Wait,
+    Done,
+}
+
+impl TileQueue {
+    fn next(&mut self) -> NextTile {
+        if self.done {
+            return NextTile::Done;
+        }
+
+        match self.pending.pop_front() {
+            Some((tile, failures)) => NextTile::Tile(tile, failures),
+            // tiles out on other workers may still come back
+            None => NextTile::Wait,
+        }
+    }
+}
+
+type TileResult = anyhow::Result<(Tile, Vec<Rgb>)>;
+
+/// Hands tiles out to workers connecting over TCP and assembles what they send back
+///
+/// Tiles out on a worker that disconnects, errors or times out are handed to another worker
+pub struct Coordinator {
+    job: Arc<RenderJob>,
+    tile_size: i32,
+    max_attempts: usize,
+    timeout: Option<Duration>,
+}
+
+impl Coordinator {
+    pub fn new(job: RenderJob) -> Self {
+        Self {
+            job: Arc::new(job),
+            tile_size: 32,
+            max_attempts: 3,
+            timeout: Some(Duration::from_secs(300)),
+        }
+    }
+
+    pub fn with_tile_size(mut self, tile_size: i32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Times a tile can be attempted before the render fails
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// How long to wait on a worker for a tile before giving up on it
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn get_job(&self) -> &RenderJob {
+        &self.job
+    }
+
+    /// Accepts workers on the listener until every tile is rendered
+    ///
+    /// Fails before accepting anyone if the job can't be sent to workers
+    pub fn run(&self, listener: TcpListener) -> anyhow::Result<Framebuffer> {
+        let job = &self.job;
+
+        // every worker gets the same bytes, encoded once
+        let mut encoded = vec![];
+        Message::Job(Box::new(RenderJob::clone(job))).write(&mut encoded)?;
+        let encoded: Arc<[u8]> = encoded.into();
+        let tiles = split_tiles(job.half_width, job.half_height, self.tile_size);
+
+        let queue = Arc::new(Mutex::new(TileQueue {
+            pending: tiles.iter().map(|tile| (*tile, 0)).collect(),
+            done: false,
+        }));
+        let (sender, receiver) = mpsc::channel();
+
+        let mut framebuffer = Framebuffer::new(job.half_width, job.half_height);
+
+        // poll so results can be collected while waiting on new workers
+        listener.set_nonblocking(true)?;
+
+        let mut remaining = tiles.len();
+        let result = loop {
+            if remaining == 0 {
+                break Ok(framebuffer);
+            }
+
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    self.spawn_worker(stream, encoded.clone(), queue.clone(), sender.clone())?
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => break Err(err.into()),
+            }
+
+            match receiver.recv_timeout(Duration::from_millis(10)) {
+                Ok(Ok((tile, pixels))) => {
+                    blit_tile(&mut framebuffer, &tile, &pixels);
+                    remaining -= 1;
+                }
+                Ok(Err(err)) => break Err(err),
+                Err(_) => {}
+            }
+        };
+
+        queue.lock().unwrap().done = true;
+
+        result
+    }
+
+    fn spawn_worker(
+        &self,
+        stream: TcpStream,
+        encoded_job: Arc<[u8]>,
+        queue: Arc<Mutex<TileQueue>>,
+        results: Sender<TileResult>,
+    ) -> anyhow::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(self.timeout)?;
+
+        let max_attempts = self.max_attempts;
+        std::thread::spawn(move || {
+            serve_worker(stream, &encoded_job, &queue, &results, max_attempts)
+        });
+
+        Ok(())
+    }
+}
+
+fn serve_worker(
+    stream: TcpStream,
+    encoded_job: &[u8],
+    queue: &Mutex<TileQueue>,
+    results: &Sender<TileResult>,
+    max_attempts: usize,
+) {
+    let Ok(write_stream) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = BufWriter::new(write_stream);
+    let mut reader = BufReader::new(stream);
+
+    // nothing is lost if the worker goes away before it has any tiles
+    if writer
+        .write_all(encoded_job)
+        .and_then(|_| writer.flush())
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let next = queue.lock().unwrap().next();
+        let (tile, failures) = match next {
+            NextTile::Tile(tile, failures) => (tile, failures),
+            NextTile::Wait => {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            NextTile::Done => {
+                let _ = Message::Done.send(&mut writer);
+                return;
+            }
+        };
+
+        match request_tile(&mut reader, &mut writer, &tile) {
+            Ok(pixels) => {
+                let _ = results.send(Ok((tile, pixels)));
+            }
+            Err(err) => {
+                if failures + 1 >= max_attempts {
+                    let _ = results.send(Err(err.context(format!(
+                        "tile {} failed {} times",
+                        tile.id,
+                        failures + 1
+                    ))));
+                } else {
+                    queue
+                        .lock()
+                        .unwrap()
+                        .pending
+                        .push_back((tile, failures + 1));
+                }
+
+                // the connection is in an unknown state, drop the worker
+                return;
+            }
+        }
+    }
+}
+
+fn request_tile(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    tile: &Tile,
+) -> anyhow::Result<Vec<Rgb>> {
+    Message::Tile(*tile).send(writer)?;
+
+    match Message::read(reader)? {
+        Message::Pixels(id, pixels) if id == tile.id => {
+            anyhow::ensure!(
+                pixels.len() == tile.get_pixel_count(),
+                "tile {} has {} pixels, expected {}",
+                tile.id,
+                pixels.len(),
+                tile.get_pixel_count()
+            );
+            Ok(pixels)
+        }
+        _ => anyhow::bail!("unexpected reply for tile {}", tile.id),
+    }
+}
+
+fn blit_tile(framebuffer: &mut Framebuffer, tile: &Tile, pixels: &[Rgb]) {
+    let rows = (tile.y..tile.y + tile.height).rev();
+    for (row, y) in pixels.chunks(tile.width as usize).zip(rows) {
+        for (pixel, x) in row.iter().zip(tile.x..) {
+            framebuffer.put_pixel(x, y, *pixel);
+        }
+    }
+}
+
+/// Connects to a coordinator and traces the tiles it hands out until it's done
+pub fn run_worker(address: impl ToSocketAddrs) -> anyhow::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    let mut reader = BufReader::new(stream);
+
+    let job = match Message::read(&mut reader)? {
+        Message::Job(job) => job,
+        _ => anyhow::bail!("expected a job from the coordinator"),
+    };
+
+    loop {
+        let message = match Message::read(&mut reader) {
+            Ok(message) => message,
+            // the coordinator may hang up as soon as the last tile is in
+            Err(err)
+                if err
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|err| err.kind() == io::ErrorKind::UnexpectedEof) =>
+            {
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+
+        match message {
+            Message::Tile(tile) => {
+                anyhow::ensure!(
+                    job.contains_tile(&tile),
+                    "tile {} is outside of the image",
+                    tile.id
+                );
+
+                let pixels = job.render_tile(&tile);
+                Message::Pixels(tile.id, pixels).send(&mut writer)?;
+            }
+            Message::Done => return Ok(()),
+            _ => anyhow::bail!("unexpected message from the coordinator"),
+        }
+    }
+}
+
+fn write_shape(w: &mut impl Write, shape: &Shape) -> anyhow::Result<()> {
+    match shape {
+        Shape::Sphere(sphere) => {
+            anyhow::ensure!(
+                sphere.get_motion().is_static(),
+                "moving spheres can't be sent to workers"
+            );
+
+            write_vec3(w, sphere.get_center())?;
+            write_f32(w, sphere.get_radius())?;
+            write_material(w, sphere.get_material())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_shape(r: &mut impl Read) -> anyhow::Result<Shape> {
+    let center = read_vec3(r)?;
+    let radius = read_f32(r)?;
+    Ok(Shape::new_sphere(center, radius, read_material(r)?))
+}
+
+fn write_material(w: &mut impl Write, material: &Material) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !material.has_textures(),
+        "textured materials can't be sent to workers"
+    );
+
+    write_u32(w, material.get_id())?;
+    write_rgb(w, material.get_color())?;
+    match material.get_shading() {
+        ShadingModel::Phong => write_u8(w, 0)?,
+        ShadingModel::MetalRoughness {
+            metallic,
+            roughness,
+            diffuse,
+        } => {
+            write_u8(w, 1)?;
+            write_f32(w, metallic)?;
+            write_f32(w, roughness)?;
+            write_u8(w, (diffuse == DiffuseModel::OrenNayar) as u8)?;
+        }
+    }
+    write_option_f32(w, material.get_shininess())?;
+    write_option_f32(w, material.get_reflectiveness())?;
+    write_option_f32(w, material.get_transparency())?;
+    write_f32(w, material.get_refractive_index())?;
+    match material.get_dispersion() {
+        None => write_u8(w, 0)?,
+        Some(Dispersion::Cauchy { a, b }) => {
+            write_u8(w, 1)?;
+            write_f32(w, a)?;
+            write_f32(w, b)?;
+        }
+        Some(Dispersion::Sellmeier { b, c }) => {
+            write_u8(w, 2)?;
+            for v in b.into_iter().chain(c) {
+                write_f32(w, v)?;
+            }
+        }
+    }
+    write_f32(w, material.get_absorption())?;
+    write_rgb(w, material.get_emission())?;
+
+    Ok(())
+}
+
+fn read_material(r: &mut impl Read) -> anyhow::Result<Material> {
+    let id = read_u32(r)?;
+    let color = read_rgb(r)?;
+    let shading = match read_u8(r)? {
+        0 => ShadingModel::Phong,
+        1 => ShadingModel::MetalRoughness {
+            metallic: read_f32(r)?,
+            roughness: read_f32(r)?,
+            diffuse: if read_u8(r)? == 1 {
+                DiffuseModel::OrenNayar
+            } else {
+                DiffuseModel::Lambert
+            },
+        },
+        tag => anyhow::bail!("unknown shading model {}", tag),
+    };
+    let shininess = read_option_f32(r)?;
+    let reflectiveness = read_option_f32(r)?;
+
+    let mut material = Material::new(color, shininess, reflectiveness)
+        .with_id(id)
+        .with_shading(shading);
+    let transparency = read_option_f32(r)?;
+    let refractive_index = read_f32(r)?;
+    if let Some(transparency) = transparency {
+        material = material.with_transparency(transparency, refractive_index);
+    }
+    match read_u8(r)? {
+        0 => {}
+        1 => material = material.with_dispersion(Dispersion::cauchy(read_f32(r)?, read_f32(r)?)),
+        2 => {
+            let b = [read_f32(r)?, read_f32(r)?, read_f32(r)?];
+            let c = [read_f32(r)?, read_f32(r)?, read_f32(r)?];
+            material = material.with_dispersion(Dispersion::sellmeier(b, c));
+        }
+        tag => anyhow::bail!("unknown dispersion {}", tag),
+    }
+
+    Ok(material
+        .with_absorption(read_f32(r)?)
+        .with_emission(read_rgb(r)?))
+}
+
+fn write_light(w: &mut impl Write, light: &Light) -> anyhow::Result<()> {
+    match light {
+        Light::Ambient(light) => {
+            write_u8(w, 0)?;
+            write_rgb(w, light.get_intensity())?;
+            match light.get_occlusion() {
+                Some(occlusion) => {
+                    write_u8(w, 1)?;
+                    write_f32(w, occlusion.get_radius())?;
+                    write_u32(w, occlusion.get_samples() as u32)?;
+                }
+                None => write_u8(w, 0)?,
+            }
+        }
+        Light::Point(light) => {
+            write_u8(w, 1)?;
+            write_rgb(w, light.get_intensity())?;
+            write_vec3(w, light.get_position())?;
+            let attenuation = light.get_attenuation();
+            write_f32(w, attenuation.get_constant())?;
+            write_f32(w, attenuation.get_linear())?;
+            write_f32(w, attenuation.get_quadratic())?;
+            write_f32(w, light.get_radius())?;
+            write_u32(w, light.get_shadow_samples() as u32)?;
+        }
+        Light::Directional(light) => {
+            write_u8(w, 2)?;
+            write_rgb(w, light.get_intensity())?;
+            write_vec3(w, light.get_direction())?;
+            write_f32(w, light.get_angular_size())?;
+            write_u32(w, light.get_shadow_samples() as u32)?;
+        }
+        _ => anyhow::bail!("only ambient, point and directional lights can be sent to workers"),
+    }
+
+    Ok(())
+}
+
+fn read_light(r: &mut impl Read) -> anyhow::Result<Light> {
+    Ok(match read_u8(r)? {
+        0 => {
+            let intensity = read_rgb(r)?;
+            match read_u8(r)? {
+                1 => Light::new_ambient_occluded(
+                    intensity,
+                    AmbientOcclusion::new(read_f32(r)?, read_u32(r)? as usize),
+                ),
+                _ => Light::new_ambient(intensity),
+            }
+        }
+        1 => {
+            let intensity = read_rgb(r)?;
+            let position = read_vec3(r)?;
+            let attenuation = Attenuation::new(read_f32(r)?, read_f32(r)?, read_f32(r)?);
+            Light::Point(
+                PointLight::new(intensity, position)
+                    .with_attenuation(attenuation)
+                    .with_radius(read_f32(r)?, read_u32(r)? as usize),
+            )
+        }
+        2 => {
+            let intensity = read_rgb(r)?;
+            let direction = read_vec3(r)?;
+            Light::Directional(
+                DirectionalLight::new(intensity, direction)
+                    .with_angular_size(read_f32(r)?, read_u32(r)? as usize),
+            )
+        }
+        tag => anyhow::bail!("unknown light {}", tag),
+    })
+}
+
+fn write_environment(w: &mut impl Write, environment: &Environment) -> anyhow::Result<()> {
+    match environment {
+        Environment::Color(color) => {
+            write_u8(w, 0)?;
+            write_rgb(w, *color)?;
+        }
+        Environment::Gradient {
+            zenith,
+            horizon,
+            ground,
+        } => {
+            write_u8(w, 1)?;
+            write_rgb(w, *zenith)?;
+            write_rgb(w, *horizon)?;
+            write_rgb(w, *ground)?;
+        }
+        _ => anyhow::bail!("only color and gradient backgrounds can be sent to workers"),
+    }
+
+    Ok(())
+}
+
+fn read_environment(r: &mut impl Read) -> anyhow::Result<Environment> {
+    Ok(match read_u8(r)? {
+        0 => Environment::color(read_rgb(r)?),
+        1 => Environment::gradient(read_rgb(r)?, read_rgb(r)?, read_rgb(r)?),
+        tag => anyhow::bail!("unknown background {}", tag),
+    })
+}
+
+// everything on the wire is little endian
+
+fn write_u8(w: &mut impl Write, v: u8) -> io::Result<()> {
+    w.write_all(&[v])
+}
+
+fn write_u32(w: &mut impl Write, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_i32(w: &mut impl Write, v: i32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_f32(w: &mut impl Write, v: f32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_option_f32(w: &mut impl Write, v: Option<f32>) -> io::Result<()> {
+    write_u8(w, v.is_some() as u8)?;
+    write_f32(w, v.unwrap_or_default())
+}
+
+fn write_vec3(w: &mut impl Write, v: Vec3) -> io::Result<()> {
+    write_f32(w, v.x)?;
+    write_f32(w, v.y)?;
+    write_f32(w, v.z)
+}
+
+fn write_rgb(w: &mut impl Write, v: Rgb) -> io::Result<()> {
+    write_f32(w, v.r)?;
+    write_f32(w, v.g)?;
+    write_f32(w, v.b)
+}
+
+fn read_bytes<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    Ok(read_bytes::<1>(r)?[0])
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_i32(r: &mut impl Read) -> io::Result<i32> {
+    Ok(i32::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_option_f32(r: &mut impl Read) -> io::Result<Option<f32>> {
+    let some = read_u8(r)? != 0;
+    let v = read_f32(r)?;
+    Ok(some.then_some(v))
+}
+
+fn read_vec3(r: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(read_f32(r)?, read_f32(r)?, read_f32(r)?))
+}
+
+fn read_rgb(r: &mut impl Read) -> io::Result<Rgb> {
+    Ok(Rgb::new(read_f32(r)?, read_f32(r)?, read_f32(r)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+
+    fn test_job() -> RenderJob {
+        RenderJob {
+            half_width: 10,
+            half_height: 7,
+            camera: Camera::default(),
+            shapes: vec![
+                Shape::new_sphere(
+                    Vec3::new(0.0, -1.0, 3.0),
+                    1.0,
+                    Material::new(Rgb::RED, Some(500.0), Some(0.2)),
+                ),
+                Shape::new_sphere(
+                    Vec3::new(-2.0, 0.0, 4.0),
+                    1.0,
+                    Material::new(Rgb::GREEN, Some(10.0), None)
+                        .with_transparency(0.5, 1.5)
+                        .with_dispersion(Dispersion::BK7),
+                ),
+            ],
+            lights: vec![
+                Light::new_ambient(Rgb::splat(0.2)),
+                Light::new_point(Rgb::splat(0.6), Vec3::new(2.0, 1.0, 0.0)),
+                Light::new_directional(Rgb::splat(0.2), Vec3::new(1.0, 4.0, 4.0)),
+            ],
+            background: Environment::gradient(Rgb::BLUE, Rgb::WHITE, Rgb::BLACK),
+            reflection_depth: 2,
+            samples: 1,
+        }
+    }
+
+    /// What the coordinator should assemble, traced locally in one tile
+    fn expected_frame(job: &RenderJob) -> Framebuffer {
+        let tile = Tile {
+            id: 0,
+            x: -job.half_width,
+            y: -job.half_height,
+            width: job.half_width * 2 + 1,
+            height: job.half_height * 2 + 1,
+        };
+
+        let mut framebuffer = Framebuffer::new(job.half_width, job.half_height);
+        blit_tile(&mut framebuffer, &tile, &job.render_tile(&tile));
+        framebuffer
+    }
+
+    fn assert_frames_eq(a: &Framebuffer, b: &Framebuffer) {
+        for x in -a.get_half_width()..=a.get_half_width() {
+            for y in -a.get_half_height()..=a.get_half_height() {
+                assert_eq!(a.get_pixel(x, y), b.get_pixel(x, y), "pixel {}, {}", x, y);
+            }
+        }
+    }
+
+    fn listen() -> (TcpListener, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        (listener, address)
+    }
+
+    fn spawn_coordinator(
+        coordinator: Coordinator,
+        listener: TcpListener,
+    ) -> JoinHandle<anyhow::Result<Framebuffer>> {
+        thread::spawn(move || coordinator.run(listener))
+    }
+
+    /// Takes the job and one tile, then misbehaves with the given reply before hanging up
+    fn faulty_worker(address: SocketAddr, reply: Option<Message>) {
+        let stream = TcpStream::connect(address).unwrap();
+        let mut writer = BufWriter::new(stream.try_clone().unwrap());
+        let mut reader = BufReader::new(stream);
+
+        assert!(matches!(
+            Message::read(&mut reader).unwrap(),
+            Message::Job(_)
+        ));
+        assert!(matches!(
+            Message::read(&mut reader).unwrap(),
+            Message::Tile(_)
+        ));
+
+        if let Some(reply) = reply {
+            reply.send(&mut writer).unwrap();
+        }
+    }
+
+    fn round_trip(message: &Message) -> Message {
+        let mut bytes = vec![];
+        message.write(&mut bytes).unwrap();
+
+        let mut reader = bytes.as_slice();
+        let message = Message::read(&mut reader).unwrap();
+        assert!(reader.is_empty(), "{} bytes left over", reader.len());
+        message
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let tile = Tile {
+            id: 7,
+            x: -3,
+            y: 2,
+            width: 4,
+            height: 5,
+        };
+        assert!(matches!(round_trip(&Message::Tile(tile)), Message::Tile(t) if t == tile));
+
+        let pixels = vec![Rgb::RED, Rgb::new(0.25, 1.5, -2.0)];
+        assert!(matches!(
+            round_trip(&Message::Pixels(3, pixels.clone())),
+            Message::Pixels(3, p) if p == pixels
+        ));
+
+        assert!(matches!(round_trip(&Message::Done), Message::Done));
+    }
+
+    #[test]
+    fn round_trips_jobs() {
+        let job = test_job();
+        let Message::Job(received) = round_trip(&Message::Job(Box::new(job.clone()))) else {
+            panic!("expected a job");
+        };
+
+        // a job that renders the same image made it across intact
+        assert_eq!(received.half_width, job.half_width);
+        assert_eq!(received.half_height, job.half_height);
+        assert_eq!(received.reflection_depth, job.reflection_depth);
+        assert_eq!(received.samples, job.samples);
+        assert_frames_eq(&expected_frame(&received), &expected_frame(&job));
+    }
+
+    #[test]
+    fn rejects_cameras_workers_cannot_reproduce() {
+        let cameras = [
+            Camera::default().with_projection(Orthographic::new(2.0)),
+            Camera::default().with_lens(0.1, 2.0),
+            Camera::default().with_bokeh(Bokeh::Polygon {
+                blades: 6,
+                rotation: 0.0,
+            }),
+            Camera::default().with_shutter(0.0, 1.0),
+            Camera::default().with_eye(0.05, 3.0),
+        ];
+
+        for camera in cameras {
+            let job = RenderJob {
+                camera: camera.clone(),
+                ..test_job()
+            };
+            assert!(
+                Message::Job(Box::new(job)).write(&mut vec![]).is_err(),
+                "{:?}",
+                camera
+            );
+        }
+    }
+
+    #[test]
+    fn fails_to_run_jobs_workers_cannot_reproduce() {
+        let job = RenderJob {
+            camera: Camera::default().with_shutter(0.0, 1.0),
+            ..test_job()
+        };
+
+        let (listener, _) = listen();
+        assert!(Coordinator::new(job).run(listener).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_image_sizes() {
+        for (half_width, half_height) in [(0, 7), (10, -1), (MAX_HALF_SIZE + 1, 7), (i32::MAX, 1)] {
+            let mut bytes = vec![];
+            write_u8(&mut bytes, 0).unwrap();
+            write_i32(&mut bytes, half_width).unwrap();
+            write_i32(&mut bytes, half_height).unwrap();
+            assert!(Message::read(&mut bytes.as_slice()).is_err());
+
+            let job = RenderJob {
+                half_width,
+                half_height,
+                ..test_job()
+            };
+            assert!(Message::Job(Box::new(job)).write(&mut vec![]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_empty_tiles() {
+        for (width, height) in [(0, 4), (4, -1), (i32::MIN, i32::MIN)] {
+            let tile = Tile {
+                id: 0,
+                x: 0,
+                y: 0,
+                width,
+                height,
+            };
+
+            let mut bytes = vec![];
+            Message::Tile(tile).write(&mut bytes).unwrap();
+            assert!(Message::read(&mut bytes.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn checks_tiles_against_the_image() {
+        let job = test_job();
+        let tile = |x, y, width, height| Tile {
+            id: 0,
+            x,
+            y,
+            width,
+            height,
+        };
+
+        assert!(job.contains_tile(&tile(-10, -7, 21, 15)));
+        assert!(!job.contains_tile(&tile(-11, -7, 4, 4)));
+        assert!(!job.contains_tile(&tile(8, 0, 4, 4)));
+        assert!(!job.contains_tile(&tile(0, 0, i32::MAX, 1)));
+    }
+
+    #[test]
+    fn assembles_tiles_from_workers() {
+        let job = test_job();
+        let expected = expected_frame(&job);
+
+        let (listener, address) = listen();
+        let coordinator = spawn_coordinator(Coordinator::new(job).with_tile_size(4), listener);
+
+        let workers: Vec<_> = (0..3)
+            .map(|_| thread::spawn(move || run_worker(address)))
+            .collect();
+
+        let framebuffer = coordinator.join().unwrap().unwrap();
+        for worker in workers {
+            worker.join().unwrap().unwrap();
+        }
+
+        assert_frames_eq(&framebuffer, &expected);
+    }
+
+    #[test]
+    fn reissues_tiles_from_failed_workers() {
+        let job = test_job();
+        let expected = expected_frame(&job);
+
+        let (listener, address) = listen();
+        let coordinator = spawn_coordinator(Coordinator::new(job).with_tile_size(4), listener);
+
+        // one hangs up with its tile, the other replies with too few pixels
+        faulty_worker(address, None);
+        faulty_worker(address, Some(Message::Pixels(0, vec![Rgb::BLACK])));
+
+        let worker = thread::spawn(move || run_worker(address));
+
+        let framebuffer = coordinator.join().unwrap().unwrap();
+        worker.join().unwrap().unwrap();
+
+        assert_frames_eq(&framebuffer, &expected);
+    }
+
+    #[test]
+    fn fails_after_max_attempts() {
+        let (listener, address) = listen();
+        let coordinator =
+            spawn_coordinator(Coordinator::new(test_job()).with_max_attempts(2), listener);
+
+        // the image fits in one tile, both attempts at it go to workers that hang up
+        faulty_worker(address, None);
+        faulty_worker(address, None);
+
+        let err = coordinator.join().unwrap().unwrap_err();
+        assert!(err.to_string().contains("failed 2 times"), "{:#}", err);
+    }
+}
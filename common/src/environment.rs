@@ -0,0 +1,252 @@
+use std::f64::consts::{FRAC_1_PI, PI};
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{DVec3, Vec2, Vec3};
+
+use crate::color::*;
+use crate::texture::*;
+
+/// Six face skybox, faces ordered +X, -X, +Y, -Y, +Z, -Z
+///
+/// Faces are seen from the inside of the cube with +Y up,
+/// the top and bottom faces have their bottom / top edge against the +Z face
+#[derive(Debug, Clone)]
+pub struct CubeMap {
+    faces: [ImageTexture; 6],
+}
+
+impl CubeMap {
+    pub fn new(faces: [ImageTexture; 6]) -> Self {
+        // wrapping would bleed the opposite edge into the face seams
+        Self {
+            faces: faces.map(|face| face.with_wrap(WrapMode::Clamp)),
+        }
+    }
+
+    pub fn load(paths: [impl AsRef<Path>; 6]) -> anyhow::Result<Self> {
+        let [px, nx, py, ny, pz, nz] = paths;
+        Ok(Self::new([
+            ImageTexture::load(px)?,
+            ImageTexture::load(nx)?,
+            ImageTexture::load(py)?,
+            ImageTexture::load(ny)?,
+            ImageTexture::load(pz)?,
+            ImageTexture::load(nz)?,
+        ]))
+    }
+
+    #[inline]
+    pub fn get_face(&self, idx: usize) -> &ImageTexture {
+        &self.faces[idx]
+    }
+
+    pub fn sample(&self, direction: DVec3) -> Rgb {
+        let d = direction;
+        let a = d.abs();
+
+        // (face, u, v, major axis)
+        let (face, u, v, major) = if a.x >= a.y && a.x >= a.z {
+            if d.x > 0.0 {
+                (0, -d.z, d.y, a.x)
+            } else {
+                (1, d.z, d.y, a.x)
+            }
+        } else if a.y >= a.z {
+            if d.y > 0.0 {
+                (2, d.x, -d.z, a.y)
+            } else {
+                (3, d.x, d.z, a.y)
+            }
+        } else if d.z > 0.0 {
+            (4, d.x, d.y, a.z)
+        } else {
+            (5, -d.x, d.y, a.z)
+        };
+
+        if major <= 0.0 {
+            return Rgb::BLACK;
+        }
+
+        let uv = Vec2::new(
+            (0.5 * (u / major + 1.0)) as f32,
+            (0.5 * (v / major + 1.0)) as f32,
+        );
+        self.faces[face].sample(uv)
+    }
+}
+
+/// Analytic sky with a sun disk
+#[derive(Debug, Copy, Clone)]
+pub struct SunSky {
+    sun_direction: Vec3,
+    sun_color: Rgb,
+    sun_size: f32,
+    zenith: Rgb,
+    horizon: Rgb,
+    ground: Rgb,
+}
+
+impl SunSky {
+    /// Clear daytime sky with the sun in the given direction
+    pub const fn new(sun_direction: Vec3) -> Self {
+        Self {
+            sun_direction,
+            sun_color: Rgb::new(20.0, 18.0, 15.0),
+            sun_size: 0.00465,
+            zenith: Rgb::new(0.25, 0.45, 0.9),
+            horizon: Rgb::new(0.8, 0.85, 0.95),
+            ground: Rgb::new(0.3, 0.28, 0.25),
+        }
+    }
+
+    /// Sun radiance and angular radius (in radians)
+    pub const fn with_sun(mut self, sun_color: Rgb, sun_size: f32) -> Self {
+        self.sun_color = sun_color;
+        self.sun_size = sun_size;
+        self
+    }
+
+    pub const fn with_sky(mut self, zenith: Rgb, horizon: Rgb, ground: Rgb) -> Self {
+        self.zenith = zenith;
+        self.horizon = horizon;
+        self.ground = ground;
+        self
+    }
+
+    #[inline]
+    pub fn get_sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    #[inline]
+    pub fn get_sun_color(&self) -> Rgb {
+        self.sun_color
+    }
+
+    #[inline]
+    pub fn get_sun_size(&self) -> f32 {
+        self.sun_size
+    }
+
+    pub fn sample(&self, direction: DVec3) -> Rgb {
+        let d = direction.normalize_or_zero();
+        let sun = self.sun_direction.as_dvec3().normalize_or_zero();
+
+        let sky = sky_gradient(d, self.zenith, self.horizon, self.ground);
+
+        if d.y < 0.0 {
+            return sky;
+        }
+
+        // the sun disk plus a faint glow around it
+        let cos_sun = d.dot(sun);
+        let glow = cos_sun.max(0.0).powi(64) * 0.02;
+        let sky = sky + self.sun_color * glow as f32;
+        if cos_sun >= (self.sun_size as f64).cos() {
+            sky + self.sun_color
+        } else {
+            sky
+        }
+    }
+}
+
+/// Blends between the zenith and horizon above the horizon and returns the ground below it
+fn sky_gradient(direction: DVec3, zenith: Rgb, horizon: Rgb, ground: Rgb) -> Rgb {
+    let d = direction.normalize_or_zero();
+    if d.y < 0.0 {
+        // soften the horizon line a little
+        return horizon.lerp(ground, ((-d.y * 10.0).min(1.0)) as f32);
+    }
+
+    horizon.lerp(zenith, (d.y.sqrt()) as f32)
+}
+
+/// What rays that don't hit any shape see
+#[derive(Debug, Clone)]
+pub enum Environment {
+    /// A constant color, as in the book
+    Color(Rgb),
+
+    /// Vertical gradient from the horizon to the zenith, constant ground
+    Gradient {
+        zenith: Rgb,
+        horizon: Rgb,
+        ground: Rgb,
+    },
+
+    SunSky(SunSky),
+
+    /// Latitude / longitude image
+    Equirectangular(Arc<ImageTexture>),
+
+    CubeMap(Arc<CubeMap>),
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::Color(Rgb::BLACK)
+    }
+}
+
+impl From<Rgb> for Environment {
+    fn from(color: Rgb) -> Self {
+        Self::Color(color)
+    }
+}
+
+impl From<SunSky> for Environment {
+    fn from(sky: SunSky) -> Self {
+        Self::SunSky(sky)
+    }
+}
+
+impl From<CubeMap> for Environment {
+    fn from(cube_map: CubeMap) -> Self {
+        Self::CubeMap(Arc::new(cube_map))
+    }
+}
+
+impl Environment {
+    pub const fn color(color: Rgb) -> Self {
+        Self::Color(color)
+    }
+
+    pub const fn gradient(zenith: Rgb, horizon: Rgb, ground: Rgb) -> Self {
+        Self::Gradient {
+            zenith,
+            horizon,
+            ground,
+        }
+    }
+
+    pub const fn sun_sky(sun_direction: Vec3) -> Self {
+        Self::SunSky(SunSky::new(sun_direction))
+    }
+
+    pub fn equirectangular(texture: ImageTexture) -> Self {
+        Self::Equirectangular(Arc::new(texture))
+    }
+
+    /// Radiance arriving from the given direction
+    pub fn sample(&self, direction: DVec3) -> Rgb {
+        match self {
+            Self::Color(color) => *color,
+            Self::Gradient {
+                zenith,
+                horizon,
+                ground,
+            } => sky_gradient(direction, *zenith, *horizon, *ground),
+            Self::SunSky(sky) => sky.sample(direction),
+            Self::Equirectangular(texture) => {
+                let d = direction.normalize_or_zero();
+
+                // longitude around +Y starting from +Z, latitude from the bottom
+                let u = 0.5 + d.x.atan2(d.z) * 0.5 * FRAC_1_PI;
+                let v = 0.5 + d.y.clamp(-1.0, 1.0).asin() / PI;
+                texture.sample(Vec2::new(u as f32, v as f32))
+            }
+            Self::CubeMap(cube_map) => cube_map.sample(direction),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use glam::IVec3;
+
+use crate::{Canvas, Rgb};
+
+/// Operator used to map HDR colors into the displayable [0, 1] range
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Clip anything above 1.0
+    #[default]
+    Clamp,
+
+    /// c / (1 + c)
+    Reinhard,
+
+    /// Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+}
+
+impl ToneMapping {
+    pub fn apply(&self, color: Rgb) -> Rgb {
+        match self {
+            Self::Clamp => color.map(|c| c.clamp(0.0, 1.0)),
+            Self::Reinhard => color.map(|c| c / (1.0 + c)),
+            Self::AcesFilmic => color.map(|c| {
+                const A: f32 = 2.51;
+                const B: f32 = 0.03;
+                const C: f32 = 2.43;
+                const D: f32 = 0.59;
+                const E: f32 = 0.14;
+
+                ((c * (A * c + B)) / (c * (C * c + D) + E)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+/// Floating-point framebuffer that keeps unclipped HDR colors
+///
+/// Uses the same centered coordinate system as the Canvas
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    half_width: i32,
+    half_height: i32,
+
+    pixels: Vec<Rgb>,
+
+    exposure: f32,
+    tone_mapping: ToneMapping,
+    gamma_correction: bool,
+}
+
+impl Framebuffer {
+    pub fn new(half_width: i32, half_height: i32) -> Self {
+        let width = (half_width * 2 + 1) as usize;
+        let height = (half_height * 2 + 1) as usize;
+
+        Self {
+            half_width,
+            half_height,
+            pixels: vec![Rgb::BLACK; width * height],
+            exposure: 0.0,
+            tone_mapping: ToneMapping::default(),
+            gamma_correction: false,
+        }
+    }
+
+    pub fn from_canvas(canvas: &Canvas) -> Self {
+        Self::new(canvas.get_half_width(), canvas.get_half_height())
+    }
+
+    /// Exposure adjustment in stops applied before tone mapping
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = tone_mapping;
+        self
+    }
+
+    /// Encode the output with the sRGB transfer function
+    pub fn with_gamma_correction(mut self, gamma_correction: bool) -> Self {
+        self.gamma_correction = gamma_correction;
+        self
+    }
+
+    #[inline]
+    pub fn get_half_width(&self) -> i32 {
+        self.half_width
+    }
+
+    #[inline]
+    pub fn get_half_height(&self) -> i32 {
+        self.half_height
+    }
+
+    #[inline]
+    pub fn get_width(&self) -> usize {
+        (self.half_width * 2 + 1) as usize
+    }
+
+    #[inline]
+    pub fn get_height(&self) -> usize {
+        (self.half_height * 2 + 1) as usize
+    }
+
+    #[inline]
+    pub fn get_exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    #[inline]
+    pub fn get_tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    #[inline]
+    fn index(&self, x: i32, y: i32) -> usize {
+        // store rows top to bottom so exporting doesn't need to flip
+        let row = (self.half_height - y) as usize;
+        let col = (self.half_width + x) as usize;
+        row * self.get_width() + col
+    }
+
+    #[inline]
+    pub fn get_pixel(&self, x: i32, y: i32) -> Rgb {
+        self.pixels[self.index(x, y)]
+    }
+
+    #[inline]
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: Rgb) {
+        let idx = self.index(x, y);
+        self.pixels[idx] = color;
+    }
+
+    pub fn clear(&mut self, color: Rgb) {
+        self.pixels.fill(color);
+    }
+
+    /// Runs the final pass on an HDR color: exposure, tone mapping, and sRGB encoding
+    pub fn resolve(&self, color: Rgb) -> Rgb {
+        let color = color * 2.0_f32.powf(self.exposure);
+        let color = self.tone_mapping.apply(color);
+
+        if self.gamma_correction {
+            color.to_srgb()
+        } else {
+            color
+        }
+    }
+
+    /// Resolves the framebuffer and writes it to the canvas
+    pub fn present(&self, canvas: &Canvas) -> anyhow::Result<()> {
+        for x in -self.half_width..=self.half_width {
+            for y in -self.half_height..=self.half_height {
+                let color = self.resolve(self.get_pixel(x, y));
+                canvas.put_pixel(IVec3::new(x, y, 1), color.into())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the unclipped framebuffer as a Radiance .hdr (RGBE) image
+    pub fn write_hdr(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        write!(
+            writer,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.get_height(),
+            self.get_width()
+        )?;
+
+        // uncompressed scanlines are valid and much simpler than RLE
+        for pixel in &self.pixels {
+            writer.write_all(&to_rgbe(*pixel))?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Shared exponent encoding used by Radiance .hdr files
+fn to_rgbe(color: Rgb) -> [u8; 4] {
+    let v = color.max_component();
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // frexp: v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2.0_f32.powi(e);
+
+    [
+        (color.r.max(0.0) * scale) as u8,
+        (color.g.max(0.0) * scale) as u8,
+        (color.b.max(0.0) * scale) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+/// Running average of rendered frames for progressive rendering
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    sum: Framebuffer,
+    frames: usize,
+}
+
+impl Accumulator {
+    pub fn new(half_width: i32, half_height: i32) -> Self {
+        Self {
+            sum: Framebuffer::new(half_width, half_height),
+            frames: 0,
+        }
+    }
+
+    pub fn from_canvas(canvas: &Canvas) -> Self {
+        Self::new(canvas.get_half_width(), canvas.get_half_height())
+    }
+
+    #[inline]
+    pub fn get_frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn reset(&mut self) {
+        self.sum.clear(Rgb::BLACK);
+        self.frames = 0;
+    }
+
+    pub fn add_frame(&mut self, frame: &Framebuffer) {
+        let half_width = self.sum.get_half_width();
+        let half_height = self.sum.get_half_height();
+
+        for x in -half_width..=half_width {
+            for y in -half_height..=half_height {
+                let color = self.sum.get_pixel(x, y) + frame.get_pixel(x, y);
+                self.sum.put_pixel(x, y, color);
+            }
+        }
+
+        self.frames += 1;
+    }
+
+    /// Writes the average of all the accumulated frames into the output framebuffer
+    pub fn resolve(&self, output: &mut Framebuffer) {
+        let scale = 1.0 / self.frames.max(1) as f32;
+
+        let half_width = self.sum.get_half_width();
+        let half_height = self.sum.get_half_height();
+
+        for x in -half_width..=half_width {
+            for y in -half_height..=half_height {
+                output.put_pixel(x, y, self.sum.get_pixel(x, y) * scale);
+            }
+        }
+    }
+}
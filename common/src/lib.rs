@@ -1,19 +1,55 @@
+mod antialiasing;
+mod aov;
 mod app;
+mod brdf;
 mod camera;
 mod canvas;
+mod color;
+mod denoise;
+mod distributed;
+mod environment;
+mod framebuffer;
 mod lights;
 mod math;
+mod media;
 mod models;
+mod path_tracer;
+mod photon_map;
+mod pick;
+mod procedural;
+mod projection;
 mod ray;
 mod scene;
 mod shapes;
+mod spectral;
+mod stats;
+mod stereo;
+mod texture;
 
+pub use antialiasing::*;
+pub use aov::*;
 pub use app::*;
+pub use brdf::*;
 pub use camera::*;
 pub use canvas::*;
+pub use color::*;
+pub use denoise::*;
+pub use distributed::*;
+pub use environment::*;
+pub use framebuffer::*;
 pub use lights::*;
 pub use math::*;
+pub use media::*;
 pub use models::*;
+pub use path_tracer::*;
+pub use photon_map::*;
+pub use pick::*;
+pub use procedural::*;
+pub use projection::*;
 pub use ray::*;
 pub use scene::*;
 pub use shapes::*;
+pub use spectral::*;
+pub use stats::*;
+pub use stereo::*;
+pub use texture::*;
@@ -1,82 +1,294 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
 use glam::{DVec3, Vec3};
+use rand::Rng;
 
+use crate::brdf::*;
+use crate::color::*;
+use crate::environment::*;
 use crate::math::*;
+use crate::photon_map::*;
 use crate::ray::*;
 use crate::shapes::*;
 
 pub(crate) const SURFACE_EPSILON: f64 = 0.001;
 
+/// Ray traced ambient occlusion
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AmbientOcclusion {
+    radius: f32,
+    samples: usize,
+}
+
+impl AmbientOcclusion {
+    /// Occluders further away than radius are ignored
+    pub const fn new(radius: f32, samples: usize) -> Self {
+        Self { radius, samples }
+    }
+
+    #[inline]
+    pub fn get_radius(&self) -> f32 {
+        self.radius
+    }
+
+    #[inline]
+    pub fn get_samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Fraction of the hemisphere around the normal that is open (1 is unoccluded)
+    pub fn evaluate(&self, point: DVec3, normal: DVec3, time: f32, shapes: &[Shape]) -> f32 {
+        let mut rng = rand::thread_rng();
+
+        // cosine weighting favors the occluders that matter most to a diffuse surface
+        let samples = self.samples.max(1);
+        let open = (0..samples)
+            .filter(|_| {
+                let l = random_cosine_hemisphere(normal, &mut rng);
+                !does_intersect(point, l, SURFACE_EPSILON, self.radius as f64, time, shapes)
+            })
+            .count();
+
+        open as f32 / samples as f32
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct AmbientLight {
-    intensity: f32,
+    intensity: Rgb,
+    occlusion: Option<AmbientOcclusion>,
 }
 
 impl AmbientLight {
-    pub const fn new(intensity: f32) -> Self {
-        Self { intensity }
+    pub const fn new(intensity: Rgb) -> Self {
+        Self {
+            intensity,
+            occlusion: None,
+        }
+    }
+
+    pub const fn with_occlusion(mut self, occlusion: AmbientOcclusion) -> Self {
+        self.occlusion = Some(occlusion);
+        self
     }
 
     #[inline]
-    pub fn get_intensity(&self) -> f32 {
+    pub fn get_intensity(&self) -> Rgb {
         self.intensity
     }
 
     #[inline]
-    pub fn get_contribution(&self) -> f32 {
+    pub fn get_occlusion(&self) -> Option<AmbientOcclusion> {
+        self.occlusion
+    }
+
+    #[inline]
+    pub fn get_contribution(&self) -> Rgb {
         self.get_intensity()
     }
+
+    /// Contribution at a surface point, darkened by ambient occlusion if enabled
+    pub fn get_occluded_contribution(
+        &self,
+        point: DVec3,
+        normal: DVec3,
+        time: f32,
+        shapes: &[Shape],
+    ) -> Rgb {
+        match self.occlusion {
+            Some(occlusion) => {
+                self.get_intensity() * occlusion.evaluate(point, normal, time, shapes)
+            }
+            None => self.get_intensity(),
+        }
+    }
 }
 
-fn diffuse_specular(
-    intensity: f32,
-    normal: DVec3,
-    l: DVec3,
-    v: DVec3,
-    shininess: Option<f32>,
-) -> f32 {
-    let intensity = intensity as f64;
-
-    // diffuse
-    let d = normal.dot(l);
-    let diffuse = if d > 0.0 {
-        intensity * (d / (normal.length() * l.length()))
-    } else {
-        0.0
-    };
-
-    // specular
-    let specular = if let Some(shininess) = shininess {
-        let r = reflect_ray(l, normal);
-
-        let d = r.dot(v);
-        if d > 0.0 {
-            intensity * (d / (r.length() * v.length())).powf(shininess as f64)
-        } else {
-            0.0
+/// Distance falloff of a light: 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Attenuation {
+    constant: f32,
+    linear: f32,
+    quadratic: f32,
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl Attenuation {
+    /// No falloff, matching the book's lights
+    pub const NONE: Self = Self::new(1.0, 0.0, 0.0);
+
+    /// Physically correct inverse square falloff
+    pub const INVERSE_SQUARE: Self = Self::new(0.0, 0.0, 1.0);
+
+    pub const fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Self {
+            constant,
+            linear,
+            quadratic,
         }
-    } else {
-        0.0
-    };
+    }
+
+    #[inline]
+    pub fn get_constant(&self) -> f32 {
+        self.constant
+    }
+
+    #[inline]
+    pub fn get_linear(&self) -> f32 {
+        self.linear
+    }
+
+    #[inline]
+    pub fn get_quadratic(&self) -> f32 {
+        self.quadratic
+    }
 
-    (diffuse + specular) as f32
+    #[inline]
+    pub fn get_factor(&self, distance: f64) -> f32 {
+        let d = distance as f32;
+        let denominator = self.constant + self.linear * d + self.quadratic * d * d;
+        if denominator > 0.0 {
+            1.0 / denominator
+        } else {
+            1.0
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct PointLight {
-    intensity: f32,
+    intensity: Rgb,
     position: Vec3,
+    attenuation: Attenuation,
+
+    radius: f32,
+    shadow_samples: usize,
 }
 
 impl PointLight {
-    pub const fn new(intensity: f32, position: Vec3) -> Self {
+    pub const fn new(intensity: Rgb, position: Vec3) -> Self {
         Self {
             intensity,
             position,
+            attenuation: Attenuation::NONE,
+            radius: 0.0,
+            shadow_samples: 1,
         }
     }
 
+    pub const fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    /// Gives the light a physical size for soft shadows,
+    /// sampled stochastically with the given number of shadow rays
+    pub const fn with_radius(mut self, radius: f32, shadow_samples: usize) -> Self {
+        self.radius = radius;
+        self.shadow_samples = shadow_samples;
+        self
+    }
+
     #[inline]
-    pub fn get_intensity(&self) -> f32 {
+    pub fn get_intensity(&self) -> Rgb {
+        self.intensity
+    }
+
+    #[inline]
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    #[inline]
+    pub fn get_attenuation(&self) -> Attenuation {
+        self.attenuation
+    }
+
+    #[inline]
+    pub fn get_radius(&self) -> f32 {
+        self.radius
+    }
+
+    #[inline]
+    pub fn get_shadow_samples(&self) -> usize {
+        self.shadow_samples
+    }
+
+    /// Vectors from the point to the sampled positions on the light
+    pub fn sample_directions(&self, point: DVec3) -> Vec<DVec3> {
+        let position = self.position.as_dvec3();
+        if self.radius <= 0.0 {
+            return vec![position - point];
+        }
+
+        sample_disk_points(position, self.radius as f64, point, self.shadow_samples)
+            .into_iter()
+            .map(|sample| sample - point)
+            .collect()
+    }
+
+    #[inline]
+    pub fn get_contribution(
+        &self,
+        point: DVec3,
+        normal: DVec3,
+        v: DVec3,
+        material: &Material,
+    ) -> Rgb {
+        let l = self.get_position().as_dvec3() - point;
+        let intensity = self.get_intensity() * self.attenuation.get_factor(l.length());
+        shade(material, intensity, normal, l, v)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpotLight {
+    intensity: Rgb,
+    position: Vec3,
+    direction: Vec3,
+    inner_angle: f32,
+    outer_angle: f32,
+    attenuation: Attenuation,
+}
+
+impl SpotLight {
+    /// Cone angles are in radians measured from the spot direction, clamped to 0..=pi and
+    /// swapped if the inner angle is the wider one.
+    /// Full intensity inside the inner angle, smoothly falling off to 0 at the outer angle,
+    /// or a hard edge when both are the same
+    pub fn new(
+        intensity: Rgb,
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        let inner_angle = inner_angle.clamp(0.0, PI);
+        let outer_angle = outer_angle.clamp(0.0, PI);
+
+        Self {
+            intensity,
+            position,
+            direction,
+            inner_angle: inner_angle.min(outer_angle),
+            outer_angle: inner_angle.max(outer_angle),
+            attenuation: Attenuation::NONE,
+        }
+    }
+
+    pub const fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    #[inline]
+    pub fn get_intensity(&self) -> Rgb {
         self.intensity
     }
 
@@ -85,35 +297,105 @@ impl PointLight {
         self.position
     }
 
+    #[inline]
+    pub fn get_direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    #[inline]
+    pub fn get_inner_angle(&self) -> f32 {
+        self.inner_angle
+    }
+
+    #[inline]
+    pub fn get_outer_angle(&self) -> f32 {
+        self.outer_angle
+    }
+
+    #[inline]
+    pub fn get_attenuation(&self) -> Attenuation {
+        self.attenuation
+    }
+
+    /// How much of the light reaches the given direction from the light
+    fn cone_factor(&self, to_point: DVec3) -> f32 {
+        let cos_angle = self
+            .direction
+            .as_dvec3()
+            .normalize_or_zero()
+            .dot(to_point.normalize_or_zero()) as f32;
+
+        let cos_inner = self.inner_angle.cos();
+        let cos_outer = self.outer_angle.cos();
+        if cos_angle >= cos_inner {
+            return 1.0;
+        }
+        // also the hard edge of a cone without a penumbra
+        if cos_angle <= cos_outer {
+            return 0.0;
+        }
+
+        // smoothstep across the penumbra
+        let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+
     #[inline]
     pub fn get_contribution(
         &self,
         point: DVec3,
         normal: DVec3,
         v: DVec3,
-        shininess: Option<f32>,
-    ) -> f32 {
+        material: &Material,
+    ) -> Rgb {
         let l = self.get_position().as_dvec3() - point;
-        diffuse_specular(self.get_intensity(), normal, l, v, shininess)
+
+        let cone = self.cone_factor(-l);
+        if cone <= 0.0 {
+            return Rgb::BLACK;
+        }
+
+        let intensity = self.get_intensity() * cone * self.attenuation.get_factor(l.length());
+        shade(material, intensity, normal, l, v)
+    }
+
+    /// Unshadowed light arriving at the given point, ignoring surface orientation
+    pub fn get_incident(&self, point: DVec3) -> Rgb {
+        let l = self.get_position().as_dvec3() - point;
+        self.get_intensity() * self.cone_factor(-l) * self.attenuation.get_factor(l.length())
     }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct DirectionalLight {
-    intensity: f32,
+    intensity: Rgb,
     direction: Vec3,
+
+    angular_size: f32,
+    shadow_samples: usize,
 }
 
 impl DirectionalLight {
-    pub const fn new(intensity: f32, direction: Vec3) -> Self {
+    pub const fn new(intensity: Rgb, direction: Vec3) -> Self {
         Self {
             intensity,
             direction,
+            angular_size: 0.0,
+            shadow_samples: 1,
         }
     }
 
+    /// Gives the light an angular diameter (in radians) for soft shadows,
+    /// sampled stochastically with the given number of shadow rays.
+    /// The sun is roughly 0.0093 radians
+    pub const fn with_angular_size(mut self, angular_size: f32, shadow_samples: usize) -> Self {
+        self.angular_size = angular_size;
+        self.shadow_samples = shadow_samples;
+        self
+    }
+
     #[inline]
-    pub fn get_intensity(&self) -> f32 {
+    pub fn get_intensity(&self) -> Rgb {
         self.intensity
     }
 
@@ -123,42 +405,444 @@ impl DirectionalLight {
     }
 
     #[inline]
-    pub fn get_contribution(&self, normal: DVec3, v: DVec3, shininess: Option<f32>) -> f32 {
+    pub fn get_angular_size(&self) -> f32 {
+        self.angular_size
+    }
+
+    #[inline]
+    pub fn get_shadow_samples(&self) -> usize {
+        self.shadow_samples
+    }
+
+    /// Directions sampled across the cone of the light
+    pub fn sample_directions(&self) -> Vec<DVec3> {
+        let direction = self.direction.as_dvec3();
+        if self.angular_size <= 0.0 {
+            return vec![direction];
+        }
+
+        // sample a disk at unit distance that subtends the angular size
+        let w = direction.normalize_or_zero();
+        let radius = (self.angular_size as f64 * 0.5).tan();
+        sample_disk_points(w, radius, DVec3::ZERO, self.shadow_samples)
+    }
+
+    #[inline]
+    pub fn get_contribution(&self, normal: DVec3, v: DVec3, material: &Material) -> Rgb {
         let l = self.get_direction().as_dvec3();
-        diffuse_specular(self.get_intensity(), normal, l, v, shininess)
+        shade(material, self.get_intensity(), normal, l, v)
+    }
+}
+
+/// Rectangular area light spanned by two edge vectors around its center
+#[derive(Debug, Copy, Clone)]
+pub struct RectLight {
+    intensity: Rgb,
+    position: Vec3,
+    u: Vec3,
+    v: Vec3,
+    samples: usize,
+    attenuation: Attenuation,
+}
+
+impl RectLight {
+    /// u and v are the full edge vectors of the rectangle.
+    /// samples is rounded down to a square number for stratification
+    pub const fn new(intensity: Rgb, position: Vec3, u: Vec3, v: Vec3, samples: usize) -> Self {
+        Self {
+            intensity,
+            position,
+            u,
+            v,
+            samples,
+            attenuation: Attenuation::NONE,
+        }
+    }
+
+    /// Falloff with the distance to the center of the rectangle
+    pub const fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    #[inline]
+    pub fn get_intensity(&self) -> Rgb {
+        self.intensity
+    }
+
+    #[inline]
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    #[inline]
+    pub fn get_u(&self) -> Vec3 {
+        self.u
+    }
+
+    #[inline]
+    pub fn get_v(&self) -> Vec3 {
+        self.v
+    }
+
+    #[inline]
+    pub fn get_samples(&self) -> usize {
+        self.samples
+    }
+
+    #[inline]
+    pub fn get_attenuation(&self) -> Attenuation {
+        self.attenuation
+    }
+
+    /// Intensity left after falling off over the distance to the point
+    #[inline]
+    fn get_attenuated_intensity(&self, point: DVec3) -> Rgb {
+        let d = (self.position.as_dvec3() - point).length();
+        self.intensity * self.attenuation.get_factor(d)
+    }
+
+    /// Jittered stratified sample points across the rectangle
+    pub fn sample_points(&self) -> Vec<DVec3> {
+        let mut rng = rand::thread_rng();
+
+        let n = ((self.samples as f64).sqrt() as usize).max(1);
+        let inv_n = 1.0 / n as f64;
+
+        let center = self.position.as_dvec3();
+        let u = self.u.as_dvec3();
+        let v = self.v.as_dvec3();
+
+        let mut points = Vec::with_capacity(n * n);
+        for i in 0..n {
+            for j in 0..n {
+                let s = (i as f64 + rng.gen::<f64>()) * inv_n - 0.5;
+                let t = (j as f64 + rng.gen::<f64>()) * inv_n - 0.5;
+                points.push(center + s * u + t * v);
+            }
+        }
+        points
     }
 }
 
+/// Spherical area light
 #[derive(Debug, Copy, Clone)]
+pub struct SphereLight {
+    intensity: Rgb,
+    position: Vec3,
+    radius: f32,
+    samples: usize,
+    attenuation: Attenuation,
+}
+
+impl SphereLight {
+    pub const fn new(intensity: Rgb, position: Vec3, radius: f32, samples: usize) -> Self {
+        Self {
+            intensity,
+            position,
+            radius,
+            samples,
+            attenuation: Attenuation::NONE,
+        }
+    }
+
+    /// Falloff with the distance to the center of the sphere
+    pub const fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    #[inline]
+    pub fn get_intensity(&self) -> Rgb {
+        self.intensity
+    }
+
+    #[inline]
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    #[inline]
+    pub fn get_radius(&self) -> f32 {
+        self.radius
+    }
+
+    #[inline]
+    pub fn get_samples(&self) -> usize {
+        self.samples
+    }
+
+    #[inline]
+    pub fn get_attenuation(&self) -> Attenuation {
+        self.attenuation
+    }
+
+    /// Intensity left after falling off over the distance to the point
+    #[inline]
+    fn get_attenuated_intensity(&self, point: DVec3) -> Rgb {
+        let d = (self.position.as_dvec3() - point).length();
+        self.intensity * self.attenuation.get_factor(d)
+    }
+
+    /// Sample points on the disk of the sphere as seen from the given point
+    pub fn sample_points(&self, point: DVec3) -> Vec<DVec3> {
+        sample_disk_points(
+            self.position.as_dvec3(),
+            self.radius as f64,
+            point,
+            self.samples,
+        )
+    }
+}
+
+/// Image based lighting from an environment
+///
+/// Irradiance is estimated with cosine-weighted shadow rays around the normal
+#[derive(Debug, Clone)]
+pub struct EnvironmentLight {
+    environment: Environment,
+    intensity: f32,
+    samples: usize,
+}
+
+impl EnvironmentLight {
+    pub const fn new(environment: Environment, samples: usize) -> Self {
+        Self {
+            environment,
+            intensity: 1.0,
+            samples,
+        }
+    }
+
+    pub const fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    #[inline]
+    pub fn get_environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    #[inline]
+    pub fn get_intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    #[inline]
+    pub fn get_samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn get_contribution(
+        &self,
+        point: DVec3,
+        normal: DVec3,
+        v: DVec3,
+        material: &Material,
+        time: f32,
+        shapes: &[Shape],
+    ) -> Rgb {
+        let mut rng = rand::thread_rng();
+
+        let samples = self.samples.max(1);
+        let scale = self.intensity / samples as f32;
+
+        (0..samples)
+            .map(|_| {
+                let l = random_cosine_hemisphere(normal, &mut rng);
+                let cos = normal.dot(l);
+                if cos <= 0.0 {
+                    return Rgb::BLACK;
+                }
+
+                let transmittance =
+                    shadow_transmittance(point, l, SURFACE_EPSILON, INFINITY, time, shapes);
+                if transmittance.is_black() {
+                    return Rgb::BLACK;
+                }
+
+                // dividing by the cosine leaves a uniform environment matching an ambient light
+                let radiance = self.environment.sample(l) * scale;
+                shade(material, radiance, normal, l, v) * transmittance / cos as f32
+            })
+            .sum()
+    }
+}
+
+/// Indirect light and caustics gathered from photon maps
+///
+/// Only the diffuse part of the surface picks up the gathered irradiance
+#[derive(Debug, Clone)]
+pub struct PhotonMapLight {
+    maps: Arc<PhotonMaps>,
+    caustics: bool,
+    indirect: bool,
+}
+
+impl PhotonMapLight {
+    pub fn new(maps: Arc<PhotonMaps>) -> Self {
+        Self {
+            maps,
+            caustics: true,
+            indirect: true,
+        }
+    }
+
+    /// Which of the photon maps are gathered
+    pub fn with_gather(mut self, caustics: bool, indirect: bool) -> Self {
+        self.caustics = caustics;
+        self.indirect = indirect;
+        self
+    }
+
+    #[inline]
+    pub fn get_maps(&self) -> &PhotonMaps {
+        &self.maps
+    }
+
+    pub fn get_irradiance(&self, point: DVec3, normal: DVec3) -> Rgb {
+        let mut irradiance = Rgb::BLACK;
+        if self.caustics {
+            irradiance += self.maps.get_caustic_irradiance(point, normal);
+        }
+        if self.indirect {
+            irradiance += self.maps.get_indirect_irradiance(point, normal);
+        }
+        irradiance
+    }
+
+    #[inline]
+    pub fn get_contribution(&self, point: DVec3, normal: DVec3, material: &Material) -> Rgb {
+        self.get_irradiance(point, normal) * material.get_color()
+    }
+}
+
+/// Samples points on a disk centered on center facing the given point
+fn sample_disk_points(center: DVec3, radius: f64, point: DVec3, samples: usize) -> Vec<DVec3> {
+    let mut rng = rand::thread_rng();
+
+    let w = (center - point).normalize_or_zero();
+    let (b1, b2) = orthonormal_basis(w);
+
+    (0..samples.max(1))
+        .map(|_| {
+            let (x, y) = random_in_unit_disk(&mut rng);
+            center + radius * (x * b1 + y * b2)
+        })
+        .collect()
+}
+
+/// Averages the shadowed contributions of a set of vectors towards a light
+///
+/// Each vector is traced as a shadow ray up to t_max
+#[allow(clippy::too_many_arguments)]
+fn sampled_contribution(
+    intensity: Rgb,
+    samples: impl AsRef<[DVec3]>,
+    t_max: f64,
+    point: DVec3,
+    normal: DVec3,
+    v: DVec3,
+    material: &Material,
+    time: f32,
+    shapes: &[Shape],
+) -> Rgb {
+    let samples = samples.as_ref();
+    let intensity = intensity / samples.len() as f32;
+
+    samples
+        .iter()
+        .map(|l| {
+            let transmittance =
+                shadow_transmittance(point, *l, SURFACE_EPSILON, t_max, time, shapes);
+            if transmittance.is_black() {
+                Rgb::BLACK
+            } else {
+                shade(material, intensity, normal, *l, v) * transmittance
+            }
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone)]
 pub enum Light {
     Ambient(AmbientLight),
     Point(PointLight),
     Directional(DirectionalLight),
+    Spot(SpotLight),
+    Rect(RectLight),
+    Sphere(SphereLight),
+    Environment(EnvironmentLight),
+    PhotonMap(PhotonMapLight),
 }
 
 impl Light {
-    pub const fn new_ambient(intensity: f32) -> Self {
+    pub const fn new_ambient(intensity: Rgb) -> Self {
         Self::Ambient(AmbientLight::new(intensity))
     }
 
-    pub const fn new_point(intensity: f32, position: Vec3) -> Self {
+    pub const fn new_ambient_occluded(intensity: Rgb, occlusion: AmbientOcclusion) -> Self {
+        Self::Ambient(AmbientLight::new(intensity).with_occlusion(occlusion))
+    }
+
+    pub const fn new_point(intensity: Rgb, position: Vec3) -> Self {
         Self::Point(PointLight::new(intensity, position))
     }
 
-    pub const fn new_directional(intensity: f32, direction: Vec3) -> Self {
+    pub const fn new_directional(intensity: Rgb, direction: Vec3) -> Self {
         Self::Directional(DirectionalLight::new(intensity, direction))
     }
+
+    pub fn new_spot(
+        intensity: Rgb,
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self::Spot(SpotLight::new(
+            intensity,
+            position,
+            direction,
+            inner_angle,
+            outer_angle,
+        ))
+    }
+
+    pub const fn new_rect(
+        intensity: Rgb,
+        position: Vec3,
+        u: Vec3,
+        v: Vec3,
+        samples: usize,
+    ) -> Self {
+        Self::Rect(RectLight::new(intensity, position, u, v, samples))
+    }
+
+    pub const fn new_sphere(intensity: Rgb, position: Vec3, radius: f32, samples: usize) -> Self {
+        Self::Sphere(SphereLight::new(intensity, position, radius, samples))
+    }
+
+    pub const fn new_environment(environment: Environment, samples: usize) -> Self {
+        Self::Environment(EnvironmentLight::new(environment, samples))
+    }
+
+    pub fn new_photon_map(maps: Arc<PhotonMaps>) -> Self {
+        Self::PhotonMap(PhotonMapLight::new(maps))
+    }
 }
 
-/// Compute the lighting at the given point with the given normal and light direction
+/// Compute the light reflected by the material at the given point with the given normal and view direction
 pub fn compute_lighting(
     point: DVec3,
     normal: DVec3,
     light_direction: DVec3,
-    shininess: Option<f32>,
+    material: &Material,
+    time: f32,
     lights: impl AsRef<[Light]>,
     shapes: impl AsRef<[Shape]>,
-) -> f32 {
+) -> Rgb {
     assert!(normal.is_normalized());
 
     let shapes = shapes.as_ref();
@@ -167,25 +851,208 @@ pub fn compute_lighting(
         .as_ref()
         .iter()
         .map(|light| match light {
-            Light::Ambient(light) => light.get_contribution(),
+            Light::Ambient(light) => {
+                light.get_occluded_contribution(point, normal, time, shapes) * material.get_color()
+            }
             Light::Point(light) => {
+                let d = (light.get_position().as_dvec3() - point).length();
+                let intensity = light.get_intensity() * light.get_attenuation().get_factor(d);
+                sampled_contribution(
+                    intensity,
+                    light.sample_directions(point),
+                    1.0,
+                    point,
+                    normal,
+                    light_direction,
+                    material,
+                    time,
+                    shapes,
+                )
+            }
+            Light::Directional(light) => sampled_contribution(
+                light.get_intensity(),
+                light.sample_directions(),
+                INFINITY,
+                point,
+                normal,
+                light_direction,
+                material,
+                time,
+                shapes,
+            ),
+            Light::Spot(light) => {
                 let l = light.get_position().as_dvec3() - point;
                 let t_max = 1.0;
-                if does_intersect(point, l, SURFACE_EPSILON, t_max, shapes) {
-                    0.0
+                let transmittance =
+                    shadow_transmittance(point, l, SURFACE_EPSILON, t_max, time, shapes);
+                if transmittance.is_black() {
+                    Rgb::BLACK
                 } else {
-                    light.get_contribution(point, normal, light_direction, shininess)
+                    light.get_contribution(point, normal, light_direction, material) * transmittance
                 }
             }
-            Light::Directional(light) => {
-                let l = light.get_direction().as_dvec3();
-                let t_max = INFINITY;
-                if does_intersect(point, l, SURFACE_EPSILON, t_max, shapes) {
-                    0.0
+            Light::Rect(light) => {
+                let samples: Vec<_> = light
+                    .sample_points()
+                    .into_iter()
+                    .map(|sample| sample - point)
+                    .collect();
+                sampled_contribution(
+                    light.get_attenuated_intensity(point),
+                    samples,
+                    1.0,
+                    point,
+                    normal,
+                    light_direction,
+                    material,
+                    time,
+                    shapes,
+                )
+            }
+            Light::Sphere(light) => {
+                let samples: Vec<_> = light
+                    .sample_points(point)
+                    .into_iter()
+                    .map(|sample| sample - point)
+                    .collect();
+                sampled_contribution(
+                    light.get_attenuated_intensity(point),
+                    samples,
+                    1.0,
+                    point,
+                    normal,
+                    light_direction,
+                    material,
+                    time,
+                    shapes,
+                )
+            }
+            Light::Environment(light) => {
+                light.get_contribution(point, normal, light_direction, material, time, shapes)
+            }
+            Light::PhotonMap(light) => light.get_contribution(point, normal, material),
+        })
+        .sum()
+}
+
+/// Averages the shadowed intensity arriving along a set of vectors towards a light
+fn sampled_incident(
+    intensity: Rgb,
+    samples: impl AsRef<[DVec3]>,
+    t_max: f64,
+    point: DVec3,
+    time: f32,
+    shapes: &[Shape],
+) -> Rgb {
+    let samples = samples.as_ref();
+    let intensity = intensity / samples.len() as f32;
+
+    samples
+        .iter()
+        .map(|l| intensity * shadow_transmittance(point, *l, SURFACE_EPSILON, t_max, time, shapes))
+        .sum()
+}
+
+/// Compute the light arriving at a point from every direction, such as inside of a volume
+///
+/// Ambient lights are left out
+pub fn compute_incident_light(
+    point: DVec3,
+    time: f32,
+    lights: impl AsRef<[Light]>,
+    shapes: impl AsRef<[Shape]>,
+) -> Rgb {
+    let shapes = shapes.as_ref();
+
+    lights
+        .as_ref()
+        .iter()
+        .map(|light| match light {
+            // ambient already stands in for all the indirect light, scattering it again counts it twice
+            Light::Ambient(_) => Rgb::BLACK,
+            Light::Point(light) => {
+                let d = (light.get_position().as_dvec3() - point).length();
+                let intensity = light.get_intensity() * light.get_attenuation().get_factor(d);
+                sampled_incident(
+                    intensity,
+                    light.sample_directions(point),
+                    1.0,
+                    point,
+                    time,
+                    shapes,
+                )
+            }
+            Light::Directional(light) => sampled_incident(
+                light.get_intensity(),
+                light.sample_directions(),
+                INFINITY,
+                point,
+                time,
+                shapes,
+            ),
+            Light::Spot(light) => {
+                let l = light.get_position().as_dvec3() - point;
+                let intensity = light.get_incident(point);
+                if intensity.is_black() {
+                    Rgb::BLACK
                 } else {
-                    light.get_contribution(normal, light_direction, shininess)
+                    intensity * shadow_transmittance(point, l, SURFACE_EPSILON, 1.0, time, shapes)
                 }
             }
+            Light::Rect(light) => {
+                let samples: Vec<_> = light
+                    .sample_points()
+                    .into_iter()
+                    .map(|sample| sample - point)
+                    .collect();
+                sampled_incident(
+                    light.get_attenuated_intensity(point),
+                    samples,
+                    1.0,
+                    point,
+                    time,
+                    shapes,
+                )
+            }
+            Light::Sphere(light) => {
+                let samples: Vec<_> = light
+                    .sample_points(point)
+                    .into_iter()
+                    .map(|sample| sample - point)
+                    .collect();
+                sampled_incident(
+                    light.get_attenuated_intensity(point),
+                    samples,
+                    1.0,
+                    point,
+                    time,
+                    shapes,
+                )
+            }
+            Light::Environment(light) => {
+                let mut rng = rand::thread_rng();
+
+                let samples = light.get_samples().max(1);
+                let scale = light.get_intensity() / samples as f32;
+
+                (0..samples)
+                    .map(|_| {
+                        let l = random_unit_vector(&mut rng);
+                        light.get_environment().sample(l)
+                            * shadow_transmittance(
+                                point,
+                                l,
+                                SURFACE_EPSILON,
+                                INFINITY,
+                                time,
+                                shapes,
+                            )
+                            * scale
+                    })
+                    .sum()
+            }
+            // photons are only stored on surfaces
+            Light::PhotonMap(_) => Rgb::BLACK,
         })
         .sum()
 }
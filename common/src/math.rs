@@ -1,4 +1,5 @@
-use glam::Vec3;
+use glam::{DVec3, Vec3};
+use rand::Rng;
 
 pub const INFINITY: f64 = f64::MAX;
 
@@ -28,6 +29,43 @@ pub fn interpolate(i0: i32, d0: f32, i1: i32, d1: f32) -> Vec<f32> {
     values
 }
 
+/// Builds two unit vectors perpendicular to n (and each other)
+pub fn orthonormal_basis(n: DVec3) -> (DVec3, DVec3) {
+    // Duff et al. "Building an Orthonormal Basis, Revisited"
+    let sign = 1.0_f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+
+    (
+        DVec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        DVec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+/// Uniformly samples a point in the unit disk
+pub fn random_in_unit_disk(rng: &mut impl Rng) -> (f64, f64) {
+    let r = rng.gen::<f64>().sqrt();
+    let theta = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Uniformly samples a direction on the unit sphere
+pub fn random_unit_vector(rng: &mut impl Rng) -> DVec3 {
+    let z = 1.0 - 2.0 * rng.gen::<f64>();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+    DVec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Samples a direction on the hemisphere around n with a cosine weighted distribution
+pub fn random_cosine_hemisphere(n: DVec3, rng: &mut impl Rng) -> DVec3 {
+    let (x, y) = random_in_unit_disk(rng);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+    let (b1, b2) = orthonormal_basis(n);
+    x * b1 + y * b2 + z * n
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Plane {
     normal: Vec3,
@@ -0,0 +1,319 @@
+use glam::{DVec3, Vec3};
+use rand::Rng;
+
+use crate::color::*;
+use crate::lights::*;
+use crate::shapes::*;
+
+/// Fog filling the whole scene, fading everything towards its color with distance
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fog {
+    /// Constant density everywhere
+    Uniform { color: Rgb, density: f32 },
+
+    /// Density falling off exponentially above the base height
+    Height {
+        color: Rgb,
+        density: f32,
+        base: f32,
+        falloff: f32,
+    },
+}
+
+impl Fog {
+    pub const fn uniform(color: Rgb, density: f32) -> Self {
+        Self::Uniform { color, density }
+    }
+
+    pub const fn height(color: Rgb, density: f32, base: f32, falloff: f32) -> Self {
+        Self::Height {
+            color,
+            density,
+            base,
+            falloff,
+        }
+    }
+
+    #[inline]
+    pub fn get_color(&self) -> Rgb {
+        match self {
+            Self::Uniform { color, .. } | Self::Height { color, .. } => *color,
+        }
+    }
+
+    /// Fraction of light that makes it through the given distance along a normalized direction
+    pub fn transmittance(&self, origin: DVec3, direction: DVec3, distance: f64) -> f32 {
+        let optical_depth = match *self {
+            Self::Uniform { density, .. } => {
+                if density <= 0.0 {
+                    return 1.0;
+                }
+                density as f64 * distance
+            }
+            Self::Height {
+                density,
+                base,
+                falloff,
+                ..
+            } => {
+                if density <= 0.0 {
+                    return 1.0;
+                }
+
+                // closed form integral of density * exp(-falloff * (y - base)) along the ray
+                let falloff = falloff as f64;
+                let density = density as f64;
+                let start = -falloff * (origin.y - base as f64);
+                let k = falloff * direction.y;
+                if k.abs() < 1e-6 {
+                    // level rays see the same density the whole way, none at all keeps 0 * inf from giving NaN
+                    let start = density * start.exp();
+                    if start <= 0.0 {
+                        return 1.0;
+                    }
+                    start * distance
+                } else {
+                    // exponents combined so a far away start can't underflow to 0 before meeting inf
+                    density * (start.exp() - (start - k * distance).exp()) / k
+                }
+            }
+        };
+
+        (-optical_depth).exp() as f32
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VolumeBounds {
+    Sphere { center: Vec3, radius: f32 },
+    Box { min: Vec3, max: Vec3 },
+}
+
+impl VolumeBounds {
+    /// Entry and exit t of the ray through the bounds
+    pub fn intersect_ray(&self, origin: DVec3, direction: DVec3) -> Option<(f64, f64)> {
+        match *self {
+            Self::Sphere { center, radius } => {
+                let r = radius as f64;
+                let co = origin - center.as_dvec3();
+
+                let a = direction.dot(direction);
+                let b = 2.0 * co.dot(direction);
+                let c = co.dot(co) - r * r;
+
+                let discriminant = b * b - 4.0 * a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+
+                let ds = discriminant.sqrt();
+                Some(((-b - ds) / (2.0 * a), (-b + ds) / (2.0 * a)))
+            }
+            Self::Box { min, max } => {
+                // slab test
+                let inv = direction.recip();
+                let t0 = (min.as_dvec3() - origin) * inv;
+                let t1 = (max.as_dvec3() - origin) * inv;
+
+                let near = t0.min(t1).max_element();
+                let far = t0.max(t1).min_element();
+                if near > far {
+                    None
+                } else {
+                    Some((near, far))
+                }
+            }
+        }
+    }
+}
+
+/// Bounded constant density volume that scatters light from the scene lights
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Volume {
+    bounds: VolumeBounds,
+    density: f32,
+    albedo: Rgb,
+}
+
+impl Volume {
+    pub const fn new(bounds: VolumeBounds, density: f32, albedo: Rgb) -> Self {
+        Self {
+            bounds,
+            density,
+            albedo,
+        }
+    }
+
+    pub const fn new_sphere(center: Vec3, radius: f32, density: f32, albedo: Rgb) -> Self {
+        Self::new(VolumeBounds::Sphere { center, radius }, density, albedo)
+    }
+
+    pub const fn new_box(min: Vec3, max: Vec3, density: f32, albedo: Rgb) -> Self {
+        Self::new(VolumeBounds::Box { min, max }, density, albedo)
+    }
+
+    #[inline]
+    pub fn get_bounds(&self) -> VolumeBounds {
+        self.bounds
+    }
+
+    #[inline]
+    pub fn get_density(&self) -> f32 {
+        self.density
+    }
+
+    #[inline]
+    pub fn get_albedo(&self) -> Rgb {
+        self.albedo
+    }
+}
+
+/// Everything rays travel through between surfaces
+#[derive(Debug, Clone)]
+pub struct Media {
+    fog: Option<Fog>,
+    volumes: Vec<Volume>,
+    steps: usize,
+}
+
+impl Default for Media {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Media {
+    /// Vacuum, as in the book
+    pub const fn new() -> Self {
+        Self {
+            fog: None,
+            volumes: Vec::new(),
+            steps: 16,
+        }
+    }
+
+    pub const fn with_fog(mut self, fog: Fog) -> Self {
+        self.fog = Some(fog);
+        self
+    }
+
+    pub fn with_volume(mut self, volume: Volume) -> Self {
+        self.volumes.push(volume);
+        self
+    }
+
+    /// Ray marching steps through each volume
+    pub const fn with_steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    #[inline]
+    pub fn get_fog(&self) -> Option<Fog> {
+        self.fog
+    }
+
+    #[inline]
+    pub fn get_volumes(&self) -> &[Volume] {
+        &self.volumes
+    }
+
+    #[inline]
+    pub fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fog.is_none() && self.volumes.is_empty()
+    }
+
+    /// (transmittance, in-scattered light) along the ray up to t_max
+    ///
+    /// Volumes only scatter once and are assumed not to overlap,
+    /// they don't shadow each other or the surfaces behind them from the lights
+    pub fn integrate(
+        &self,
+        origin: DVec3,
+        direction: DVec3,
+        t_max: f64,
+        time: f32,
+        lights: &[Light],
+        shapes: &[Shape],
+    ) -> (Rgb, Rgb) {
+        if self.is_empty() {
+            return (Rgb::WHITE, Rgb::BLACK);
+        }
+
+        let length = direction.length();
+        let d = direction / length;
+
+        let mut transmittance = Rgb::WHITE;
+        let mut scattered = Rgb::BLACK;
+
+        // composite the volumes back to front
+        let mut segments: Vec<_> = self
+            .volumes
+            .iter()
+            .filter_map(|volume| {
+                let (t0, t1) = volume.get_bounds().intersect_ray(origin, direction)?;
+                let (t0, t1) = (t0.max(0.0), t1.min(t_max));
+                (t0 < t1).then_some((volume, t0, t1))
+            })
+            .collect();
+        segments.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        for (volume, t0, t1) in segments {
+            let (volume_transmittance, volume_scattered) =
+                self.march(volume, origin, direction, t0, t1, time, lights, shapes);
+            transmittance *= volume_transmittance;
+            scattered = scattered * volume_transmittance + volume_scattered;
+        }
+
+        if let Some(fog) = self.fog {
+            // rays that miss everything are fogged out completely
+            let fog_transmittance = fog.transmittance(origin, d, t_max * length);
+            transmittance *= fog_transmittance;
+            scattered = scattered * fog_transmittance + fog.get_color() * (1.0 - fog_transmittance);
+        }
+
+        (transmittance, scattered)
+    }
+
+    /// Single scattering ray march through a volume between t0 and t1
+    #[allow(clippy::too_many_arguments)]
+    fn march(
+        &self,
+        volume: &Volume,
+        origin: DVec3,
+        direction: DVec3,
+        t0: f64,
+        t1: f64,
+        time: f32,
+        lights: &[Light],
+        shapes: &[Shape],
+    ) -> (f32, Rgb) {
+        let steps = self.steps.max(1);
+        let dt = (t1 - t0) / steps as f64;
+        let step_transmittance = (-volume.get_density() as f64 * dt * direction.length()).exp();
+
+        // jitter the start so banding turns into noise
+        let jitter = rand::thread_rng().gen::<f64>();
+
+        let mut transmittance = 1.0;
+        let mut scattered = Rgb::BLACK;
+        for step in 0..steps {
+            let t = t0 + (step as f64 + jitter) * dt;
+            let point = origin + t * direction;
+
+            let incident = compute_incident_light(point, time, lights, shapes);
+            scattered += incident
+                * volume.get_albedo()
+                * (transmittance * (1.0 - step_transmittance)) as f32;
+
+            transmittance *= step_transmittance;
+        }
+
+        (transmittance as f32, scattered)
+    }
+}
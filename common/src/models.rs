@@ -1,7 +1,6 @@
 use glam::{Mat4, Quat, Vec3, Vec4};
-use sdl2::pixels::Color;
 
-use crate::{Canvas, Material, Plane, Triangle};
+use crate::{Canvas, Material, Plane, Projection, Rgb, Triangle};
 
 #[derive(Debug, Clone)]
 pub struct Transform {
@@ -135,19 +134,19 @@ const CUBE_VERTICES: [Vec3; 8] = [
     Vec3::new(1.0, -1.0, -1.0),
 ];
 
-const CUBE_TRIANGLES: [Triangle; 12] = [
-    Triangle::new(0, 1, 2, Material::new(Color::RED, None, None)),
-    Triangle::new(0, 2, 3, Material::new(Color::RED, None, None)),
-    Triangle::new(4, 0, 3, Material::new(Color::GREEN, None, None)),
-    Triangle::new(4, 3, 7, Material::new(Color::GREEN, None, None)),
-    Triangle::new(5, 4, 7, Material::new(Color::BLUE, None, None)),
-    Triangle::new(5, 7, 6, Material::new(Color::BLUE, None, None)),
-    Triangle::new(1, 5, 6, Material::new(Color::YELLOW, None, None)),
-    Triangle::new(1, 6, 2, Material::new(Color::YELLOW, None, None)),
-    Triangle::new(4, 5, 1, Material::new(Color::MAGENTA, None, None)),
-    Triangle::new(4, 1, 0, Material::new(Color::MAGENTA, None, None)),
-    Triangle::new(2, 6, 7, Material::new(Color::CYAN, None, None)),
-    Triangle::new(2, 7, 3, Material::new(Color::CYAN, None, None)),
+static CUBE_TRIANGLES: [Triangle; 12] = [
+    Triangle::new(0, 1, 2, Material::new(Rgb::RED, None, None)),
+    Triangle::new(0, 2, 3, Material::new(Rgb::RED, None, None)),
+    Triangle::new(4, 0, 3, Material::new(Rgb::GREEN, None, None)),
+    Triangle::new(4, 3, 7, Material::new(Rgb::GREEN, None, None)),
+    Triangle::new(5, 4, 7, Material::new(Rgb::BLUE, None, None)),
+    Triangle::new(5, 7, 6, Material::new(Rgb::BLUE, None, None)),
+    Triangle::new(1, 5, 6, Material::new(Rgb::YELLOW, None, None)),
+    Triangle::new(1, 6, 2, Material::new(Rgb::YELLOW, None, None)),
+    Triangle::new(4, 5, 1, Material::new(Rgb::MAGENTA, None, None)),
+    Triangle::new(4, 1, 0, Material::new(Rgb::MAGENTA, None, None)),
+    Triangle::new(2, 6, 7, Material::new(Rgb::CYAN, None, None)),
+    Triangle::new(2, 7, 3, Material::new(Rgb::CYAN, None, None)),
 ];
 
 #[derive(Debug, Default, Clone)]
@@ -186,14 +185,19 @@ impl Model {
     }
 
     // transform should be in camera space
-    pub fn render(&self, canvas: &Canvas, transform: &Transform) -> anyhow::Result<()> {
+    pub fn render(
+        &self,
+        canvas: &Canvas,
+        projection: &impl Projection,
+        transform: &Transform,
+    ) -> anyhow::Result<()> {
         let mut projected = Vec::with_capacity(self.get_vertices().len());
         for v in self.get_vertices() {
             // model space to camera space
             let v = transform * v.extend(1.0);
 
             // camera space to viewport
-            projected.push(canvas.project(&v.truncate()));
+            projected.push(project_vertex(canvas, projection, &v.truncate())?);
         }
 
         for t in self.get_triangles() {
@@ -211,6 +215,7 @@ pub struct Instance {
 }
 
 impl Instance {
+    #[allow(clippy::default_constructed_unit_structs)]
     pub fn new_cube(transform: Transform) -> Self {
         Self {
             model: Model::Cube(Cube::default()),
@@ -243,7 +248,7 @@ impl Instance {
         instance
     }
 
-    pub fn render(&self, canvas: &Canvas) -> anyhow::Result<()> {
+    pub fn render(&self, canvas: &Canvas, projection: &impl Projection) -> anyhow::Result<()> {
         let model = self.get_model();
 
         let mut projected = Vec::with_capacity(model.get_vertices().len());
@@ -252,7 +257,7 @@ impl Instance {
             let v = self.get_transform() * v.extend(1.0);
 
             // world space to viewport
-            projected.push(canvas.project(&v.truncate()));
+            projected.push(project_vertex(canvas, projection, &v.truncate())?);
         }
 
         for t in model.get_triangles() {
@@ -262,3 +267,10 @@ impl Instance {
         Ok(())
     }
 }
+
+#[inline]
+fn project_vertex(canvas: &Canvas, projection: &impl Projection, v: &Vec3) -> anyhow::Result<Vec3> {
+    canvas
+        .project_with(projection, v)
+        .ok_or_else(|| anyhow::anyhow!("only linear projections can be rasterized"))
+}
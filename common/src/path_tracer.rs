@@ -0,0 +1,219 @@
+use glam::DVec3;
+use rand::Rng;
+
+use crate::brdf::*;
+use crate::color::*;
+use crate::environment::*;
+use crate::lights::*;
+use crate::math::*;
+use crate::media::*;
+use crate::ray::*;
+use crate::shapes::*;
+use crate::stats::*;
+
+/// Bounces before Russian roulette is allowed to terminate a path
+const RUSSIAN_ROULETTE_DEPTH: usize = 3;
+
+/// Direct lighting from the scene lights (next event estimation)
+///
+/// Ambient, environment and photon map lights are skipped since the path tracer computes
+/// indirect lighting itself and picks up the environment when a path escapes
+fn direct_lighting(
+    point: DVec3,
+    normal: DVec3,
+    v: DVec3,
+    material: &Material,
+    time: f32,
+    lights: &[Light],
+    shapes: &[Shape],
+) -> Rgb {
+    lights
+        .iter()
+        .filter(|light| {
+            !matches!(
+                light,
+                Light::Ambient(_) | Light::Environment(_) | Light::PhotonMap(_)
+            )
+        })
+        .map(|light| {
+            compute_lighting(
+                point,
+                normal,
+                v,
+                material,
+                time,
+                std::slice::from_ref(light),
+                shapes,
+            )
+        })
+        .sum()
+}
+
+/// Trace a Monte Carlo path starting with the given ray and return the estimated radiance
+///
+/// Diffuse bounces are cosine-weighted, mirror and glass materials
+/// pick between reflection / refraction stochastically,
+/// and lights are sampled directly at every diffuse vertex.
+/// Every bounce and shadow ray of the path sees the shapes at the same time,
+/// and a path with a wavelength in nanometers refracts through dispersive materials at it
+#[allow(clippy::too_many_arguments)]
+pub fn trace_path(
+    origin: DVec3,
+    direction: DVec3,
+    t_min: f64,
+    t_max: f64,
+    time: f32,
+    wavelength: Option<f32>,
+    max_depth: usize,
+    lights: impl AsRef<[Light]>,
+    shapes: impl AsRef<[Shape]>,
+    background: &Environment,
+    media: &Media,
+    rng: &mut impl Rng,
+) -> Rgb {
+    let lights = lights.as_ref();
+    let shapes = shapes.as_ref();
+
+    let mut radiance = Rgb::BLACK;
+    let mut throughput = Rgb::WHITE;
+
+    let mut origin = origin;
+    let mut direction = direction;
+    let mut t_min = t_min;
+    let mut t_max = t_max;
+
+    record(|stats| stats.primary_rays += 1);
+
+    for depth in 0..=max_depth {
+        let (closest_shape_idx, closest_t) =
+            closest_intersection(origin, direction, t_min, t_max, time, shapes);
+        let closest_shape_idx = match closest_shape_idx {
+            Some(idx) => idx,
+            None => {
+                let (transmittance, scattered) =
+                    media.integrate(origin, direction, t_max, time, lights, shapes);
+                radiance += throughput * (background.sample(direction) * transmittance + scattered);
+                break;
+            }
+        };
+
+        let closest_shape = &shapes[closest_shape_idx];
+
+        let p = origin + closest_t * direction;
+        let n = closest_shape.get_normal(p, time);
+        let inside = direction.dot(n) > 0.0;
+
+        let texture_point = closest_shape.get_texture_point(p, time);
+        let n = closest_shape.get_material().shading_normal(
+            n,
+            closest_shape.get_tangent(p, time),
+            &texture_point,
+        );
+        let n = if inside { -n } else { n };
+
+        let material = closest_shape.get_material().resolve(&texture_point);
+
+        if inside {
+            throughput = absorb(throughput, &material, closest_t * direction.length());
+        } else {
+            let (transmittance, scattered) =
+                media.integrate(origin, direction, closest_t, time, lights, shapes);
+            radiance += throughput * scattered;
+            throughput *= transmittance;
+        }
+
+        // lights are sampled directly so emission can always be added
+        // without double counting
+        radiance += throughput * material.get_emission();
+
+        if depth == max_depth {
+            break;
+        }
+
+        let d = direction.normalize();
+        let reflectiveness = material.get_reflectiveness().unwrap_or(0.0);
+
+        direction = match material.get_transparency() {
+            Some(transparency) if rng.gen::<f32>() < transparency => {
+                let ior = match (material.get_dispersion(), wavelength) {
+                    (Some(dispersion), Some(wavelength)) => {
+                        dispersion.get_refractive_index(wavelength)
+                    }
+                    _ => material.get_refractive_index(),
+                } as f64;
+                let (n1, n2) = if inside { (ior, 1.0) } else { (1.0, ior) };
+
+                let fresnel = schlick(-n.dot(d), n1, n2);
+                match refract_ray(d, n, n1 / n2) {
+                    Some(refracted) if rng.gen::<f64>() >= fresnel => {
+                        record(|stats| stats.refraction_rays += 1);
+                        refracted
+                    }
+                    _ => {
+                        record(|stats| stats.reflection_rays += 1);
+                        reflect_ray(-d, n)
+                    }
+                }
+            }
+            _ if rng.gen::<f32>() < reflectiveness => {
+                record(|stats| stats.reflection_rays += 1);
+                reflect_ray(-d, n)
+            }
+            _ => match material.get_shading() {
+                ShadingModel::Phong => {
+                    // Phong materials are treated as purely lambertian
+                    let albedo = material.get_color();
+                    let lambert = Material::from(albedo);
+
+                    radiance +=
+                        throughput * direct_lighting(p, n, -d, &lambert, time, lights, shapes);
+
+                    // the cosine term and pdf cancel out leaving just the albedo
+                    throughput *= albedo;
+                    record(|stats| stats.reflection_rays += 1);
+                    random_cosine_hemisphere(n, rng)
+                }
+                ShadingModel::MetalRoughness {
+                    metallic,
+                    roughness,
+                    diffuse,
+                } => {
+                    radiance +=
+                        throughput * direct_lighting(p, n, -d, &material, time, lights, shapes);
+
+                    let sample = sample_brdf(
+                        material.get_color(),
+                        metallic,
+                        roughness,
+                        diffuse,
+                        n,
+                        -d,
+                        rng,
+                    );
+                    match sample {
+                        Some((l, weight)) => {
+                            throughput *= weight;
+                            record(|stats| stats.reflection_rays += 1);
+                            l
+                        }
+                        None => break,
+                    }
+                }
+            },
+        };
+
+        if depth >= RUSSIAN_ROULETTE_DEPTH {
+            let survival = throughput.max_component().min(0.95);
+            if rng.gen::<f32>() >= survival {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+
+        origin = p;
+        t_min = SURFACE_EPSILON;
+        t_max = INFINITY;
+    }
+
+    radiance
+}
@@ -0,0 +1,445 @@
+use std::f32::consts::PI;
+
+use glam::{DVec3, Vec3};
+use rand::Rng;
+
+use crate::color::*;
+use crate::lights::*;
+use crate::math::*;
+use crate::pick::*;
+use crate::ray::*;
+use crate::shapes::*;
+
+/// Light flux stored where a photon landed on a diffuse surface
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Photon {
+    pub position: Vec3,
+
+    /// Direction the photon was travelling in
+    pub direction: Vec3,
+    pub power: Rgb,
+}
+
+/// Photons in a balanced kd-tree for nearest neighbor gathers
+///
+/// The tree is implicit: each range of photons is split at its middle photon
+/// along the axis stored with it
+#[derive(Debug, Default, Clone)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build_tree(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Up to count photons closest to the point within max_distance, nearest first
+    pub fn nearest(&self, point: Vec3, count: usize, max_distance: f32) -> Vec<&Photon> {
+        let mut found = vec![];
+        self.search(
+            0,
+            self.photons.len(),
+            point,
+            max_distance * max_distance,
+            &mut found,
+        );
+
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found.truncate(count);
+        found
+            .into_iter()
+            .map(|(_, idx)| &self.photons[idx])
+            .collect()
+    }
+
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        point: Vec3,
+        max_distance2: f32,
+        found: &mut Vec<(f32, usize)>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let photon = &self.photons[mid];
+        let axis = self.axes[mid];
+
+        let delta = point[axis] - photon.position[axis];
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+
+        self.search(near.0, near.1, point, max_distance2, found);
+
+        let distance2 = point.distance_squared(photon.position);
+        if distance2 <= max_distance2 {
+            found.push((distance2, mid));
+        }
+
+        // the far side can only hold photons if the splitting plane is in range
+        if delta * delta <= max_distance2 {
+            self.search(far.0, far.1, point, max_distance2, found);
+        }
+    }
+
+    /// Density estimate of the flux arriving at a surface point with the given normal
+    pub fn get_irradiance(&self, point: Vec3, normal: Vec3, count: usize, radius: f32) -> Rgb {
+        let photons = self.nearest(point, count, radius);
+        if photons.is_empty() {
+            return Rgb::BLACK;
+        }
+
+        // shrink the disk to the photons found once there are enough of them
+        let radius2 = if photons.len() == count {
+            photons.last().map_or(radius * radius, |photon| {
+                point.distance_squared(photon.position)
+            })
+        } else {
+            radius * radius
+        };
+
+        let flux: Rgb = photons
+            .iter()
+            .filter(|photon| photon.direction.dot(normal) < 0.0)
+            .map(|photon| photon.power)
+            .sum();
+
+        flux / (PI * radius2.max(f32::EPSILON))
+    }
+}
+
+fn build_tree(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+
+    // split along the widest extent
+    let (min, max) = photons.iter().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), photon| (min.min(photon.position), max.max(photon.position)),
+    );
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+    axes[mid] = axis;
+
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build_tree(left, left_axes);
+    build_tree(&mut right[1..], &mut right_axes[1..]);
+}
+
+/// Caustic and indirect photon maps for a scene
+#[derive(Debug, Default, Clone)]
+pub struct PhotonMaps {
+    caustic: PhotonMap,
+    global: PhotonMap,
+
+    gather_count: usize,
+    gather_radius: f32,
+}
+
+impl PhotonMaps {
+    #[inline]
+    pub fn get_caustic(&self) -> &PhotonMap {
+        &self.caustic
+    }
+
+    #[inline]
+    pub fn get_global(&self) -> &PhotonMap {
+        &self.global
+    }
+
+    /// Light focused onto the point by reflective and refractive surfaces
+    pub fn get_caustic_irradiance(&self, point: DVec3, normal: DVec3) -> Rgb {
+        self.caustic.get_irradiance(
+            point.as_vec3(),
+            normal.as_vec3(),
+            self.gather_count,
+            self.gather_radius,
+        )
+    }
+
+    /// Light arriving at the point after bouncing off of at least one diffuse surface
+    pub fn get_indirect_irradiance(&self, point: DVec3, normal: DVec3) -> Rgb {
+        self.global.get_irradiance(
+            point.as_vec3(),
+            normal.as_vec3(),
+            self.gather_count,
+            self.gather_radius,
+        )
+    }
+}
+
+/// First pass of photon mapping: shoots photons from the point lights into the scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhotonMapper {
+    photons: usize,
+    max_bounces: usize,
+    intensity: f32,
+    indirect: bool,
+
+    gather_count: usize,
+    gather_radius: f32,
+}
+
+impl Default for PhotonMapper {
+    fn default() -> Self {
+        Self::new(100_000)
+    }
+}
+
+impl PhotonMapper {
+    /// Shoots the given number of photons from each point light
+    pub const fn new(photons: usize) -> Self {
+        Self {
+            photons,
+            max_bounces: 8,
+            intensity: 1.0,
+            indirect: true,
+            gather_count: 100,
+            gather_radius: 0.25,
+        }
+    }
+
+    pub const fn with_max_bounces(mut self, max_bounces: usize) -> Self {
+        self.max_bounces = max_bounces;
+        self
+    }
+
+    /// Scales the power of every photon
+    pub const fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Whether to store indirect photons, disable when an ambient light stands in for them
+    pub const fn with_indirect(mut self, indirect: bool) -> Self {
+        self.indirect = indirect;
+        self
+    }
+
+    /// Number of photons and the largest radius used to estimate irradiance
+    pub const fn with_gather(mut self, count: usize, radius: f32) -> Self {
+        self.gather_count = count;
+        self.gather_radius = radius;
+        self
+    }
+
+    #[inline]
+    pub fn get_photons(&self) -> usize {
+        self.photons
+    }
+
+    /// Traces photons from every point light and sorts them into photon maps
+    ///
+    /// Photons fall off with the light's attenuation rather than physically,
+    /// so lights without attenuation light caustics as brightly as they light surfaces directly.
+    /// Moving shapes are frozen where they start
+    pub fn build(&self, lights: impl AsRef<[Light]>, shapes: impl AsRef<[Shape]>) -> PhotonMaps {
+        let shapes = shapes.as_ref();
+        let mut rng = rand::thread_rng();
+
+        let mut caustic = vec![];
+        let mut global = vec![];
+
+        let count = self.photons.max(1);
+        for light in lights.as_ref() {
+            let Light::Point(light) = light else {
+                continue;
+            };
+
+            // an isotropic point light emits 4pi times its intensity
+            let power = light.get_intensity() * (4.0 * PI * self.intensity / count as f32);
+            let origin = light.get_position().as_dvec3();
+
+            for _ in 0..count {
+                let direction = random_unit_vector(&mut rng);
+                self.trace_photon(
+                    origin,
+                    direction,
+                    power,
+                    light.get_attenuation(),
+                    shapes,
+                    &mut rng,
+                    &mut caustic,
+                    &mut global,
+                );
+            }
+        }
+
+        PhotonMaps {
+            caustic: PhotonMap::new(caustic),
+            global: PhotonMap::new(global),
+            gather_count: self.gather_count,
+            gather_radius: self.gather_radius,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn trace_photon(
+        &self,
+        mut origin: DVec3,
+        mut direction: DVec3,
+        mut power: Rgb,
+        attenuation: Attenuation,
+        shapes: &[Shape],
+        rng: &mut impl Rng,
+        caustic: &mut Vec<Photon>,
+        global: &mut Vec<Photon>,
+    ) {
+        let mut t_min = 0.0;
+        let mut specular = false;
+        let mut diffuse = false;
+
+        for bounce in 0..=self.max_bounces {
+            let Some(hit) = query_ray(origin, direction, t_min, INFINITY, 0.0, shapes) else {
+                return;
+            };
+
+            if bounce == 0 {
+                // trade the physical 1/d^2 falloff for the light's own
+                let d = hit.t * direction.length();
+                power *= (d * d) as f32 * attenuation.get_factor(d);
+            }
+
+            let direction_n = direction.normalize();
+            let material = &hit.material;
+
+            let r = material.get_reflectiveness().unwrap_or(0.0);
+            let transparency = material.get_transparency().unwrap_or(0.0) * (1.0 - r);
+
+            // only the diffuse part of the surface shows photons in the gather,
+            // direct light is already handled by the light itself
+            let diffuse_part = 1.0 - r - transparency;
+            if diffuse_part > 0.0 {
+                let stored = power * diffuse_part;
+                if specular && !diffuse {
+                    caustic.push(photon(&hit, direction_n, stored));
+                } else if diffuse && self.indirect {
+                    global.push(photon(&hit, direction_n, stored));
+                }
+            }
+
+            // russian roulette picks what happens to the photon
+            let u = rng.gen::<f32>();
+
+            let inside = shapes[hit.shape].get_normal(hit.point, 0.0).dot(direction) > 0.0;
+            let n = hit.normal;
+
+            direction = if u < r {
+                specular = true;
+                reflect_ray(-direction_n, n)
+            } else if u < r + transparency {
+                specular = true;
+
+                let ior = material.get_refractive_index() as f64;
+                let (n1, n2) = if inside { (ior, 1.0) } else { (1.0, ior) };
+                let fresnel = schlick(-n.dot(direction_n), n1, n2);
+                match refract_ray(direction_n, n, n1 / n2) {
+                    Some(refracted) if rng.gen::<f64>() >= fresnel => refracted,
+                    _ => reflect_ray(-direction_n, n),
+                }
+            } else {
+                // only surfaces that reflect more light keep more photons
+                let color = material.get_color();
+                let survival = color.max_component().min(1.0);
+                if survival <= 0.0 || rng.gen::<f32>() >= survival {
+                    return;
+                }
+
+                // past a diffuse bounce only the global map takes photons
+                if !self.indirect {
+                    return;
+                }
+
+                diffuse = true;
+                power *= color / survival;
+                random_cosine_hemisphere(n, rng)
+            };
+
+            origin = hit.point;
+            t_min = SURFACE_EPSILON;
+        }
+    }
+}
+
+#[inline]
+fn photon(hit: &ShapeHit, direction: DVec3, power: Rgb) -> Photon {
+    Photon {
+        position: hit.point.as_vec3(),
+        direction: direction.as_vec3(),
+        power,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn random_point(rng: &mut impl Rng) -> Vec3 {
+        Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let photons: Vec<_> = (0..2000)
+            .map(|_| Photon {
+                position: random_point(&mut rng),
+                direction: Vec3::Y,
+                power: Rgb::WHITE,
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+
+        for (count, max_distance) in [(1, 2.0), (10, 0.3), (50, 0.25), (200, 4.0)] {
+            for _ in 0..50 {
+                let point = random_point(&mut rng);
+
+                let mut expected: Vec<_> = photons
+                    .iter()
+                    .filter(|photon| photon.position.distance(point) <= max_distance)
+                    .collect();
+                expected.sort_by(|a, b| {
+                    let a = a.position.distance_squared(point);
+                    a.total_cmp(&b.position.distance_squared(point))
+                });
+                expected.truncate(count);
+
+                assert_eq!(map.nearest(point, count, max_distance), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use glam::{DVec3, Vec2, Vec3};
+
+use crate::camera::*;
+use crate::canvas::*;
+use crate::math::*;
+use crate::projection::*;
+use crate::ray::*;
+use crate::scene::*;
+use crate::shapes::*;
+
+/// What a ray hit in the raytraced scene
+#[derive(Debug, Clone)]
+pub struct ShapeHit {
+    /// Index of the shape in the queried shapes
+    pub shape: usize,
+    pub t: f64,
+    pub point: DVec3,
+
+    /// Shading normal, facing the ray
+    pub normal: DVec3,
+    pub uv: Vec2,
+
+    /// Material with its textures resolved at the hit point
+    pub material: Material,
+}
+
+/// Find the closest shape along the given ray along with its surface details
+pub fn query_ray(
+    origin: DVec3,
+    direction: DVec3,
+    t_min: f64,
+    t_max: f64,
+    time: f32,
+    shapes: impl AsRef<[Shape]>,
+) -> Option<ShapeHit> {
+    let shapes = shapes.as_ref();
+
+    let (closest_shape_idx, closest_t) =
+        closest_intersection(origin, direction, t_min, t_max, time, shapes);
+    let closest_shape_idx = closest_shape_idx?;
+    let closest_shape = &shapes[closest_shape_idx];
+
+    let p = origin + closest_t * direction;
+    let n = closest_shape.get_normal(p, time);
+    let inside = direction.dot(n) > 0.0;
+
+    let texture_point = closest_shape.get_texture_point(p, time);
+    let n = closest_shape.get_material().shading_normal(
+        n,
+        closest_shape.get_tangent(p, time),
+        &texture_point,
+    );
+    let n = if inside { -n } else { n };
+
+    Some(ShapeHit {
+        shape: closest_shape_idx,
+        t: closest_t,
+        point: p,
+        normal: n,
+        uv: texture_point.uv,
+        material: closest_shape
+            .get_material()
+            .resolve(&texture_point)
+            .into_owned(),
+    })
+}
+
+/// Find the shape under the given canvas pixel
+pub fn pick_shape(
+    canvas: &Canvas,
+    camera: &Camera,
+    x: i32,
+    y: i32,
+    shapes: impl AsRef<[Shape]>,
+) -> Option<ShapeHit> {
+    let (origin, direction) = camera.get_center_ray(canvas.to_viewport(x, y))?;
+    query_ray(
+        origin,
+        direction,
+        1.0,
+        INFINITY,
+        camera.get_shutter_open(),
+        shapes,
+    )
+}
+
+/// What a pixel covers in the rasterized scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InstanceHit {
+    /// Index of the instance in the scene
+    pub instance: usize,
+
+    /// Index of the triangle in the instance model
+    pub triangle: usize,
+
+    /// Camera space depth of the triangle under the pixel
+    pub depth: f32,
+}
+
+/// Find the closest instance triangle under the given canvas pixel
+///
+/// None if the camera projection can't be rasterized
+pub fn pick_instance(
+    canvas: &Canvas,
+    camera: &Camera,
+    scene: &Scene,
+    x: i32,
+    y: i32,
+) -> Option<InstanceHit> {
+    let m_camera = camera.get_matrix();
+    let projection = camera.get_projection();
+    let pixel = Vec2::new(x as f32, y as f32);
+
+    let mut closest: Option<InstanceHit> = None;
+    for (instance_idx, instance) in scene.get_instances().iter().enumerate() {
+        // same transforms as Scene::render
+        let m = m_camera * instance.get_transform();
+
+        let model = instance.get_model();
+        let vertices: Vec<Vec3> = model
+            .get_vertices()
+            .iter()
+            .map(|v| (&m * v.extend(1.0)).truncate())
+            .collect();
+
+        for (triangle_idx, triangle) in model.get_triangles().iter().enumerate() {
+            let [a, b, c] = triangle.get_vertices().map(|idx| vertices[idx]);
+
+            // nothing is clipped yet, so skip anything behind the camera
+            if a.z <= 0.0 || b.z <= 0.0 || c.z <= 0.0 {
+                continue;
+            }
+
+            let (pa, pb, pc) = (
+                canvas.project_with(&projection, &a)?.truncate(),
+                canvas.project_with(&projection, &b)?.truncate(),
+                canvas.project_with(&projection, &c)?.truncate(),
+            );
+
+            let (w0, w1, w2) = match barycentric(pixel, pa, pb, pc) {
+                Some(weights) => weights,
+                None => continue,
+            };
+
+            // z interpolates linearly under a parallel projection, otherwise 1/z does
+            let depth = match projection {
+                CameraProjection::Orthographic(_) => w0 * a.z + w1 * b.z + w2 * c.z,
+                _ => 1.0 / (w0 / a.z + w1 / b.z + w2 / c.z),
+            };
+
+            // is_none_or would need Rust 1.82
+            #[allow(clippy::unnecessary_map_or)]
+            let closer = closest.map_or(true, |hit| depth < hit.depth);
+            if closer {
+                closest = Some(InstanceHit {
+                    instance: instance_idx,
+                    triangle: triangle_idx,
+                    depth,
+                });
+            }
+        }
+    }
+
+    closest
+}
+
+/// Barycentric weights of p in the 2D triangle, None if p is outside of it
+fn barycentric(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> Option<(f32, f32, f32)> {
+    let area = (b - a).perp_dot(c - a);
+    if area.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let w0 = (b - p).perp_dot(c - p) / area;
+    let w1 = (c - p).perp_dot(a - p) / area;
+    let w2 = 1.0 - w0 - w1;
+
+    (w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0).then_some((w0, w1, w2))
+}
@@ -0,0 +1,280 @@
+use glam::Vec3;
+
+use crate::{Rgb, TexturePoint};
+
+/// Which coordinates a procedural texture is evaluated in
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextureSpace {
+    /// 2D surface texture coordinates
+    Uv,
+
+    /// 3D position relative to the shape, moves with the shape
+    #[default]
+    Object,
+
+    /// 3D world position
+    World,
+}
+
+/// Procedural patterns blending between two colors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pattern {
+    Checker,
+    Stripes,
+
+    /// Linear ramp along x, repeating every unit
+    Gradient,
+
+    /// Perlin noise
+    Noise,
+
+    /// Fractal Brownian motion (summed octaves of signed noise)
+    Fbm {
+        octaves: u32,
+    },
+
+    /// Summed octaves of absolute noise
+    Turbulence {
+        octaves: u32,
+    },
+
+    /// Sine bands along x perturbed by turbulence
+    Marble {
+        octaves: u32,
+        strength: f32,
+    },
+
+    /// Concentric rings around the y axis perturbed by noise
+    Wood {
+        strength: f32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProceduralTexture {
+    pattern: Pattern,
+    a: Rgb,
+    b: Rgb,
+    space: TextureSpace,
+    scale: f32,
+}
+
+impl ProceduralTexture {
+    /// scale is the number of pattern repeats per unit
+    pub const fn new(pattern: Pattern, a: Rgb, b: Rgb, space: TextureSpace, scale: f32) -> Self {
+        Self {
+            pattern,
+            a,
+            b,
+            space,
+            scale,
+        }
+    }
+
+    pub const fn checker(a: Rgb, b: Rgb, space: TextureSpace, scale: f32) -> Self {
+        Self::new(Pattern::Checker, a, b, space, scale)
+    }
+
+    pub const fn stripes(a: Rgb, b: Rgb, space: TextureSpace, scale: f32) -> Self {
+        Self::new(Pattern::Stripes, a, b, space, scale)
+    }
+
+    pub const fn marble(a: Rgb, b: Rgb, space: TextureSpace, scale: f32) -> Self {
+        Self::new(
+            Pattern::Marble {
+                octaves: 6,
+                strength: 5.0,
+            },
+            a,
+            b,
+            space,
+            scale,
+        )
+    }
+
+    pub const fn wood(a: Rgb, b: Rgb, space: TextureSpace, scale: f32) -> Self {
+        Self::new(Pattern::Wood { strength: 0.3 }, a, b, space, scale)
+    }
+
+    #[inline]
+    pub fn get_pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    #[inline]
+    pub fn get_space(&self) -> TextureSpace {
+        self.space
+    }
+
+    #[inline]
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Pattern value in [0, 1] at the given point
+    pub fn evaluate(&self, p: Vec3) -> f32 {
+        let p = p * self.scale;
+
+        match self.pattern {
+            Pattern::Checker => {
+                let sum = p.x.floor() + p.y.floor() + p.z.floor();
+                if sum.rem_euclid(2.0) < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Pattern::Stripes => {
+                if p.x.floor().rem_euclid(2.0) < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Pattern::Gradient => p.x.rem_euclid(1.0),
+            Pattern::Noise => (perlin(p) * 0.5 + 0.5).clamp(0.0, 1.0),
+            Pattern::Fbm { octaves } => (fbm(p, octaves) * 0.5 + 0.5).clamp(0.0, 1.0),
+            Pattern::Turbulence { octaves } => turbulence(p, octaves).clamp(0.0, 1.0),
+            Pattern::Marble { octaves, strength } => {
+                let t = p.x + strength * turbulence(p, octaves);
+                0.5 + 0.5 * t.sin()
+            }
+            Pattern::Wood { strength } => {
+                let r = (p.x * p.x + p.z * p.z).sqrt() + strength * perlin(p);
+                r.rem_euclid(1.0)
+            }
+        }
+    }
+
+    pub fn sample(&self, point: &TexturePoint) -> Rgb {
+        let p = match self.space {
+            TextureSpace::Uv => point.uv.extend(0.0),
+            TextureSpace::Object => point.object,
+            TextureSpace::World => point.world,
+        };
+
+        self.a.lerp(self.b, self.evaluate(p))
+    }
+}
+
+/// Ken Perlin's reference permutation
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+#[inline]
+fn hash(i: i32) -> i32 {
+    PERMUTATION[(i & 255) as usize] as i32
+}
+
+#[inline]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of 12 gradient directions picked by the hash
+#[inline]
+fn grad(hash: i32, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Improved Perlin noise, roughly in [-1, 1]
+pub fn perlin(p: Vec3) -> f32 {
+    let xi = p.x.floor() as i32;
+    let yi = p.y.floor() as i32;
+    let zi = p.z.floor() as i32;
+
+    let x = p.x - p.x.floor();
+    let y = p.y - p.y.floor();
+    let z = p.z - p.z.floor();
+
+    let u = fade(x);
+    let v = fade(y);
+    let w = fade(z);
+
+    let a = hash(xi) + yi;
+    let aa = hash(a) + zi;
+    let ab = hash(a + 1) + zi;
+    let b = hash(xi + 1) + yi;
+    let ba = hash(b) + zi;
+    let bb = hash(b + 1) + zi;
+
+    lerp(
+        lerp(
+            lerp(grad(hash(aa), x, y, z), grad(hash(ba), x - 1.0, y, z), u),
+            lerp(
+                grad(hash(ab), x, y - 1.0, z),
+                grad(hash(bb), x - 1.0, y - 1.0, z),
+                u,
+            ),
+            v,
+        ),
+        lerp(
+            lerp(
+                grad(hash(aa + 1), x, y, z - 1.0),
+                grad(hash(ba + 1), x - 1.0, y, z - 1.0),
+                u,
+            ),
+            lerp(
+                grad(hash(ab + 1), x, y - 1.0, z - 1.0),
+                grad(hash(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+                u,
+            ),
+            v,
+        ),
+        w,
+    )
+}
+
+/// Fractal Brownian motion, roughly in [-1, 1]
+pub fn fbm(p: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    for _ in 0..octaves {
+        sum += amplitude * perlin(p * frequency);
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum
+}
+
+/// Turbulence, roughly in [0, 1]
+pub fn turbulence(p: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    for _ in 0..octaves {
+        sum += amplitude * perlin(p * frequency).abs();
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum
+}
@@ -0,0 +1,236 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use glam::{Vec2, Vec3};
+
+/// Maps viewport points to camera space rays and back
+///
+/// Viewport points are in the book's 1 x 1 viewport (x and y in [-0.5, 0.5]),
+/// ray directions are scaled so t = 1 lies on the projection surface at the viewport distance
+pub trait Projection {
+    /// Camera space (origin, direction) of the ray through the given viewport point
+    ///
+    /// None if the point is outside of the projected image
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)>;
+
+    /// Viewport point the given camera space point projects to
+    ///
+    /// Only linear projections (straight lines stay straight) can be rasterized
+    fn project(&self, _v: Vec3, _distance: f32) -> Option<Vec2> {
+        None
+    }
+
+    #[inline]
+    fn is_linear(&self) -> bool {
+        false
+    }
+}
+
+/// Pinhole projection from the book
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Perspective;
+
+impl Projection for Perspective {
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)> {
+        Some((Vec3::ZERO, viewport.extend(distance)))
+    }
+
+    fn project(&self, v: Vec3, distance: f32) -> Option<Vec2> {
+        Some(Vec2::new(v.x * distance / v.z, v.y * distance / v.z))
+    }
+
+    #[inline]
+    fn is_linear(&self) -> bool {
+        true
+    }
+}
+
+/// Parallel projection, the viewport covers scale world units
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Orthographic {
+    scale: f32,
+}
+
+impl Default for Orthographic {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl Orthographic {
+    pub const fn new(scale: f32) -> Self {
+        Self { scale }
+    }
+
+    #[inline]
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+}
+
+impl Projection for Orthographic {
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)> {
+        Some(((viewport * self.scale).extend(0.0), Vec3::Z * distance))
+    }
+
+    fn project(&self, v: Vec3, _distance: f32) -> Option<Vec2> {
+        Some(v.truncate() / self.scale)
+    }
+
+    #[inline]
+    fn is_linear(&self) -> bool {
+        true
+    }
+}
+
+/// Equidistant fisheye, the angle from the view axis grows linearly with the distance
+/// from the viewport center, reaching fov / 2 at the viewport edge
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fisheye {
+    fov: f32,
+}
+
+impl Default for Fisheye {
+    fn default() -> Self {
+        Self::new(PI)
+    }
+}
+
+impl Fisheye {
+    /// fov in radians, up to 2 pi
+    pub const fn new(fov: f32) -> Self {
+        Self { fov }
+    }
+
+    #[inline]
+    pub fn get_fov(&self) -> f32 {
+        self.fov
+    }
+}
+
+impl Projection for Fisheye {
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)> {
+        // the image is a circle inscribed in the viewport
+        let r = viewport.length() / 0.5;
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.fov * 0.5;
+        let phi = viewport.y.atan2(viewport.x);
+
+        let direction = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        Some((Vec3::ZERO, direction * distance))
+    }
+}
+
+/// Full 360 x 180 degree latitude / longitude panorama
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)> {
+        let longitude = viewport.x / 0.5 * PI;
+        let latitude = viewport.y / 0.5 * FRAC_PI_2;
+
+        let direction = Vec3::new(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            latitude.cos() * longitude.cos(),
+        );
+        Some((Vec3::ZERO, direction * distance))
+    }
+}
+
+/// Cylindrical panorama covering fov horizontally,
+/// vertical lines stay straight and keep the same angular scale as the center
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cylindrical {
+    fov: f32,
+}
+
+impl Default for Cylindrical {
+    fn default() -> Self {
+        Self::new(PI)
+    }
+}
+
+impl Cylindrical {
+    /// Horizontal fov in radians, up to 2 pi
+    pub const fn new(fov: f32) -> Self {
+        Self { fov }
+    }
+
+    #[inline]
+    pub fn get_fov(&self) -> f32 {
+        self.fov
+    }
+}
+
+impl Projection for Cylindrical {
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)> {
+        let longitude = viewport.x * self.fov;
+
+        let direction = Vec3::new(longitude.sin(), viewport.y * self.fov, longitude.cos());
+        Some((Vec3::ZERO, direction * distance))
+    }
+}
+
+/// Projection used by a camera
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum CameraProjection {
+    #[default]
+    Perspective,
+    Orthographic(Orthographic),
+    Fisheye(Fisheye),
+    Equirectangular,
+    Cylindrical(Cylindrical),
+}
+
+impl From<Orthographic> for CameraProjection {
+    fn from(projection: Orthographic) -> Self {
+        Self::Orthographic(projection)
+    }
+}
+
+impl From<Fisheye> for CameraProjection {
+    fn from(projection: Fisheye) -> Self {
+        Self::Fisheye(projection)
+    }
+}
+
+impl From<Cylindrical> for CameraProjection {
+    fn from(projection: Cylindrical) -> Self {
+        Self::Cylindrical(projection)
+    }
+}
+
+impl Projection for CameraProjection {
+    fn get_ray(&self, viewport: Vec2, distance: f32) -> Option<(Vec3, Vec3)> {
+        match self {
+            Self::Perspective => Perspective.get_ray(viewport, distance),
+            Self::Orthographic(projection) => projection.get_ray(viewport, distance),
+            Self::Fisheye(projection) => projection.get_ray(viewport, distance),
+            Self::Equirectangular => Equirectangular.get_ray(viewport, distance),
+            Self::Cylindrical(projection) => projection.get_ray(viewport, distance),
+        }
+    }
+
+    fn project(&self, v: Vec3, distance: f32) -> Option<Vec2> {
+        match self {
+            Self::Perspective => Perspective.project(v, distance),
+            Self::Orthographic(projection) => projection.project(v, distance),
+            Self::Fisheye(projection) => projection.project(v, distance),
+            Self::Equirectangular => Equirectangular.project(v, distance),
+            Self::Cylindrical(projection) => projection.project(v, distance),
+        }
+    }
+
+    #[inline]
+    fn is_linear(&self) -> bool {
+        matches!(self, Self::Perspective | Self::Orthographic(_))
+    }
+}
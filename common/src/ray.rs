@@ -1,27 +1,72 @@
 use glam::DVec3;
-use sdl2::pixels::Color;
 
+use crate::color::*;
+use crate::environment::*;
 use crate::lights::*;
 use crate::math::*;
+use crate::media::*;
 use crate::shapes::*;
+use crate::stats::*;
 
 /// Reflects a ray around a normal
 pub fn reflect_ray(r: DVec3, n: DVec3) -> DVec3 {
     2.0 * n * n.dot(r) - r
 }
 
+/// Refracts a ray through a surface using Snell's law
+///
+/// The direction should be normalized, the normal should face against it,
+/// and eta is the ratio of the refractive indices (n1 / n2).
+/// Returns None on total internal reflection.
+pub fn refract_ray(direction: DVec3, n: DVec3, eta: f64) -> Option<DVec3> {
+    let cos_i = -n.dot(direction);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(eta * direction + (eta * cos_i - cos_t) * n)
+}
+
+/// Schlick's approximation of the Fresnel reflectance
+/// going from a medium with index n1 into a medium with index n2
+pub fn schlick(cos_i: f64, n1: f64, n2: f64) -> f64 {
+    let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+
+    let mut cos_x = cos_i;
+    if n1 > n2 {
+        // going into a less dense medium uses the transmitted angle
+        let eta = n1 / n2;
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            // total internal reflection
+            return 1.0;
+        }
+        cos_x = (1.0 - sin2_t).sqrt();
+    }
+
+    r0 + (1.0 - r0) * (1.0 - cos_x).powi(5)
+}
+
 /// Checks to see if the given ray intersects a shape
 pub fn does_intersect(
     origin: DVec3,
     direction: DVec3,
     t_min: f64,
     t_max: f64,
+    time: f32,
     shapes: impl AsRef<[Shape]>,
 ) -> bool {
     let shapes = shapes.as_ref();
 
+    record(|stats| {
+        stats.shadow_rays += 1;
+        stats.intersection_tests += shapes.len() as u64;
+    });
+
     shapes.iter().any(|shape| {
-        if let Some((t1, t2)) = shape.intersect_ray(origin, direction) {
+        if let Some((t1, t2)) = shape.intersect_ray(origin, direction, time) {
             if (t_min..=t_max).contains(&t1) {
                 return true;
             }
@@ -35,22 +80,66 @@ pub fn does_intersect(
     })
 }
 
-/// Finds the shape closest to the origin that intersects the ray between t_min / t_max
+/// Computes how much light makes it along the given ray
+///
+/// Opaque shapes block the light entirely while transparent shapes filter it by their color
+pub fn shadow_transmittance(
+    origin: DVec3,
+    direction: DVec3,
+    t_min: f64,
+    t_max: f64,
+    time: f32,
+    shapes: impl AsRef<[Shape]>,
+) -> Rgb {
+    let shapes = shapes.as_ref();
+
+    record(|stats| {
+        stats.shadow_rays += 1;
+        stats.intersection_tests += shapes.len() as u64;
+    });
+
+    let mut transmittance = Rgb::WHITE;
+    for shape in shapes {
+        if let Some((t1, t2)) = shape.intersect_ray(origin, direction, time) {
+            if !(t_min..=t_max).contains(&t1) && !(t_min..=t_max).contains(&t2) {
+                continue;
+            }
+
+            let material = shape.get_material();
+            match material.get_transparency() {
+                Some(transparency) => transmittance *= material.get_color() * transparency,
+                None => return Rgb::BLACK,
+            }
+
+            if transmittance.is_black() {
+                return Rgb::BLACK;
+            }
+        }
+    }
+
+    transmittance
+}
+
+/// Finds the shape closest to the origin that intersects the ray between t_min / t_max,
+/// with moving shapes where they are at the given time
 pub fn closest_intersection(
     origin: DVec3,
     direction: DVec3,
     t_min: f64,
     t_max: f64,
+    time: f32,
     shapes: impl AsRef<[Shape]>,
 ) -> (Option<usize>, f64) {
     let shapes = shapes.as_ref();
 
+    record(|stats| stats.intersection_tests += shapes.len() as u64);
+
     let mut closest_t = INFINITY;
     let mut closest_shape_idx = None;
 
     // TODO: there's probably a better method for this
     shapes.iter().enumerate().for_each(|(idx, shape)| {
-        if let Some((t1, t2)) = shape.intersect_ray(origin, direction) {
+        if let Some((t1, t2)) = shape.intersect_ray(origin, direction, time) {
             if (t_min..=t_max).contains(&t1) && t1 < closest_t {
                 closest_t = t1;
                 closest_shape_idx = Some(idx);
@@ -73,17 +162,23 @@ pub fn trace_ray_no_lights(
     direction: DVec3,
     t_min: f64,
     t_max: f64,
+    time: f32,
     shapes: impl AsRef<[Shape]>,
-    background: Color,
-) -> Color {
+    background: &Environment,
+) -> Rgb {
     let shapes = shapes.as_ref();
 
+    record(|stats| {
+        stats.primary_rays += 1;
+        stats.intersection_tests += shapes.len() as u64;
+    });
+
     let mut closest_t = INFINITY;
     let mut closest_shape_idx = None;
 
     // TODO: there's probably a better method for this
     shapes.iter().enumerate().for_each(|(idx, shape)| {
-        if let Some((t1, t2)) = shape.intersect_ray(origin, direction) {
+        if let Some((t1, t2)) = shape.intersect_ray(origin, direction, time) {
             if (t_min..=t_max).contains(&t1) && t1 < closest_t {
                 closest_t = t1;
                 closest_shape_idx = Some(idx);
@@ -97,9 +192,44 @@ pub fn trace_ray_no_lights(
     });
 
     if let Some(closest_shape_idx) = closest_shape_idx {
-        shapes[closest_shape_idx].get_material().get_color()
+        let closest_shape = &shapes[closest_shape_idx];
+
+        let p = origin + closest_t * direction;
+        let material = closest_shape
+            .get_material()
+            .resolve(&closest_shape.get_texture_point(p, time));
+        material.get_color()
     } else {
-        background
+        background.sample(direction)
+    }
+}
+
+/// Trace the given ray and return the ambient occlusion of the intersection as a gray level
+///
+/// Rays that miss everything are white
+pub fn trace_ambient_occlusion(
+    origin: DVec3,
+    direction: DVec3,
+    t_min: f64,
+    t_max: f64,
+    time: f32,
+    shapes: impl AsRef<[Shape]>,
+    occlusion: &AmbientOcclusion,
+) -> Rgb {
+    let shapes = shapes.as_ref();
+
+    let (closest_shape_idx, closest_t) =
+        closest_intersection(origin, direction, t_min, t_max, time, shapes);
+    if let Some(closest_shape_idx) = closest_shape_idx {
+        let closest_shape = &shapes[closest_shape_idx];
+
+        let p = origin + closest_t * direction;
+        let n = closest_shape.get_normal(p, time);
+        let n = if direction.dot(n) > 0.0 { -n } else { n };
+
+        Rgb::splat(occlusion.evaluate(p, n, time, shapes))
+    } else {
+        Rgb::WHITE
     }
 }
 
@@ -110,60 +240,150 @@ pub fn trace_ray(
     direction: DVec3,
     t_min: f64,
     t_max: f64,
+    time: f32,
     reflection_depth: usize,
     lights: impl AsRef<[Light]>,
     shapes: impl AsRef<[Shape]>,
-    background: Color,
-) -> Color {
-    let lights = lights.as_ref();
-    let shapes = shapes.as_ref();
+    background: &Environment,
+    media: &Media,
+) -> Rgb {
+    record(|stats| stats.primary_rays += 1);
 
+    trace(
+        origin,
+        direction,
+        t_min,
+        t_max,
+        time,
+        reflection_depth,
+        lights.as_ref(),
+        shapes.as_ref(),
+        background,
+        media,
+    )
+}
+
+/// Recursive part of trace_ray, secondary rays are counted by the caller
+#[allow(clippy::too_many_arguments)]
+fn trace(
+    origin: DVec3,
+    direction: DVec3,
+    t_min: f64,
+    t_max: f64,
+    time: f32,
+    reflection_depth: usize,
+    lights: &[Light],
+    shapes: &[Shape],
+    background: &Environment,
+    media: &Media,
+) -> Rgb {
     let (closest_shape_idx, closest_t) =
-        closest_intersection(origin, direction, t_min, t_max, shapes);
+        closest_intersection(origin, direction, t_min, t_max, time, shapes);
     if let Some(closest_shape_idx) = closest_shape_idx {
         let closest_shape = &shapes[closest_shape_idx];
 
         let p = origin + closest_t * direction;
-        let n = p - closest_shape.get_center().as_dvec3();
-        let n = n.normalize_or_zero();
+        let n = closest_shape.get_normal(p, time);
 
-        let material = closest_shape.get_material();
-        let l = compute_lighting(p, n, -direction, material.get_shininess(), lights, shapes);
+        // if we hit the back of the surface, we're inside of the shape
+        let inside = direction.dot(n) > 0.0;
 
-        let color = material.get_color();
-        let local_color = Color::RGB(
-            (color.r as f32 * l) as u8,
-            (color.g as f32 * l) as u8,
-            (color.b as f32 * l) as u8,
+        let texture_point = closest_shape.get_texture_point(p, time);
+        let n = closest_shape.get_material().shading_normal(
+            n,
+            closest_shape.get_tangent(p, time),
+            &texture_point,
         );
+        let n = if inside { -n } else { n };
 
-        if reflection_depth == 0 {
-            return local_color;
-        }
+        let material = closest_shape.get_material().resolve(&texture_point);
+        let local_color = compute_lighting(p, n, -direction, &material, time, lights, shapes)
+            + material.get_emission();
 
-        let r = material.get_reflectiveness();
-        if let Some(r) = r {
-            let reflected = reflect_ray(-direction, n);
-            let reflected_color = trace_ray(
-                p,
-                reflected,
-                SURFACE_EPSILON,
-                INFINITY,
-                reflection_depth - 1,
-                lights,
-                shapes,
-                background,
-            );
-
-            Color::RGB(
-                (local_color.r as f32 * (1.0 - r) + reflected_color.r as f32 * r) as u8,
-                (local_color.g as f32 * (1.0 - r) + reflected_color.g as f32 * r) as u8,
-                (local_color.b as f32 * (1.0 - r) + reflected_color.b as f32 * r) as u8,
-            )
-        } else {
+        let color = if reflection_depth == 0 {
             local_color
+        } else {
+            let r = material.get_reflectiveness().unwrap_or(0.0);
+            let transparency = material.get_transparency();
+
+            let reflected_color = if r > 0.0 || transparency.is_some() {
+                let reflected = reflect_ray(-direction, n);
+                record(|stats| stats.reflection_rays += 1);
+                trace(
+                    p,
+                    reflected,
+                    SURFACE_EPSILON,
+                    INFINITY,
+                    time,
+                    reflection_depth - 1,
+                    lights,
+                    shapes,
+                    background,
+                    media,
+                )
+            } else {
+                local_color
+            };
+
+            let color = local_color.lerp(reflected_color, r);
+
+            if let Some(transparency) = transparency {
+                let ior = material.get_refractive_index() as f64;
+                let (n1, n2) = if inside { (ior, 1.0) } else { (1.0, ior) };
+
+                let d = direction.normalize();
+                let cos_i = -n.dot(d);
+
+                // total internal reflection is handled by schlick returning 1.0
+                let fresnel = schlick(cos_i, n1, n2) as f32;
+                let refracted_color = if let Some(refracted) = refract_ray(d, n, n1 / n2) {
+                    record(|stats| stats.refraction_rays += 1);
+                    trace(
+                        p,
+                        refracted,
+                        SURFACE_EPSILON,
+                        INFINITY,
+                        time,
+                        reflection_depth - 1,
+                        lights,
+                        shapes,
+                        background,
+                        media,
+                    )
+                } else {
+                    reflected_color
+                };
+
+                let transmitted = refracted_color.lerp(reflected_color, fresnel);
+                color.lerp(transmitted, transparency)
+            } else {
+                color
+            }
+        };
+
+        if inside {
+            // the ray travelled through the shape to get here
+            let distance = closest_t * direction.length();
+            absorb(color, &material, distance)
+        } else {
+            let (transmittance, scattered) =
+                media.integrate(origin, direction, closest_t, time, lights, shapes);
+            color * transmittance + scattered
         }
     } else {
-        background
+        let (transmittance, scattered) =
+            media.integrate(origin, direction, t_max, time, lights, shapes);
+        background.sample(direction) * transmittance + scattered
     }
 }
+
+/// Beer's law absorption of light travelling the given distance through a material
+pub(crate) fn absorb(color: Rgb, material: &Material, distance: f64) -> Rgb {
+    let absorption = material.get_absorption();
+    if absorption <= 0.0 {
+        return color;
+    }
+
+    let density = (Rgb::WHITE - material.get_color()) * absorption;
+    color * density.map(|d| (-d * distance as f32).exp())
+}
@@ -31,12 +31,13 @@ impl Scene {
     // TODO: should the camera be a member of the scene?
     pub fn render(&self, canvas: &Canvas, camera: &Camera) -> anyhow::Result<()> {
         let m_camera = camera.get_matrix();
+        let projection = camera.get_projection();
 
         for instance in self.get_instances() {
             // world space to camera space
             let m = m_camera * instance.get_transform();
 
-            instance.get_model().render(canvas, &m)?;
+            instance.get_model().render(canvas, &projection, &m)?;
         }
 
         Ok(())
@@ -1,45 +1,248 @@
-use glam::{DVec3, Vec3};
-use sdl2::pixels::Color;
+use std::borrow::Cow;
+use std::f64::consts::PI;
 
-use crate::Canvas;
+use glam::{DVec3, Vec2, Vec3};
 
-#[derive(Debug, Copy, Clone)]
+use crate::{
+    orthonormal_basis, Canvas, DiffuseModel, Dispersion, NormalMap, Rgb, ShadingModel, Texture,
+    TexturePoint,
+};
+
+#[derive(Debug, Clone)]
 pub struct Material {
-    color: Color,
+    /// Identifies the material in AOV passes
+    id: u32,
+
+    color: Rgb,
+    shading: ShadingModel,
     shininess: Option<f32>,
     reflectiveness: Option<f32>,
+
+    transparency: Option<f32>,
+    refractive_index: f32,
+    dispersion: Option<Dispersion>,
+    absorption: f32,
+
+    emission: Rgb,
+
+    color_texture: Option<Texture>,
+    shininess_texture: Option<Texture>,
+    reflectiveness_texture: Option<Texture>,
+
+    normal_map: Option<NormalMap>,
 }
 
 impl Default for Material {
     fn default() -> Self {
-        Self {
-            color: Color::WHITE,
-            shininess: None,
-            reflectiveness: None,
-        }
+        Self::new(Rgb::WHITE, None, None)
     }
 }
 
-impl From<Color> for Material {
-    fn from(color: Color) -> Self {
+impl From<Rgb> for Material {
+    fn from(color: Rgb) -> Self {
         Self::new(color, None, None)
     }
 }
 
 impl Material {
-    pub const fn new(color: Color, shininess: Option<f32>, reflectiveness: Option<f32>) -> Self {
+    pub const fn new(color: Rgb, shininess: Option<f32>, reflectiveness: Option<f32>) -> Self {
+        Self::from_parts(color, None, shininess, reflectiveness)
+    }
+
+    /// Material whose color comes from a texture
+    ///
+    /// This is separate from with_color_texture so that procedural textures can be const
+    pub const fn new_textured(
+        texture: Texture,
+        shininess: Option<f32>,
+        reflectiveness: Option<f32>,
+    ) -> Self {
+        Self::from_parts(Rgb::WHITE, Some(texture), shininess, reflectiveness)
+    }
+
+    const fn from_parts(
+        color: Rgb,
+        color_texture: Option<Texture>,
+        shininess: Option<f32>,
+        reflectiveness: Option<f32>,
+    ) -> Self {
         Self {
+            id: 0,
             color,
+            shading: ShadingModel::Phong,
             shininess,
             reflectiveness,
+            transparency: None,
+            refractive_index: 1.0,
+            dispersion: None,
+            absorption: 0.0,
+            emission: Rgb::BLACK,
+            color_texture,
+            shininess_texture: None,
+            reflectiveness_texture: None,
+            normal_map: None,
         }
     }
 
+    /// Physically based material, metallic and roughness are in [0, 1]
+    pub const fn new_metal_roughness(color: Rgb, metallic: f32, roughness: f32) -> Self {
+        Self::new(color, None, None).with_shading(ShadingModel::MetalRoughness {
+            metallic,
+            roughness,
+            diffuse: DiffuseModel::Lambert,
+        })
+    }
+
+    pub const fn with_id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub const fn with_shading(mut self, shading: ShadingModel) -> Self {
+        self.shading = shading;
+        self
+    }
+
+    /// Makes the material transparent with the given index of refraction
+    /// (1.0 for air, ~1.33 for water, ~1.5 for glass)
+    pub const fn with_transparency(mut self, transparency: f32, refractive_index: f32) -> Self {
+        self.transparency = Some(transparency);
+        self.refractive_index = refractive_index;
+        self
+    }
+
+    /// Makes the refractive index depend on the wavelength in spectral renders,
+    /// RGB renders keep using the index given to with_transparency
+    pub const fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    /// Sets the Beer's law absorption density for light travelling through the material
+    ///
+    /// Light is absorbed in proportion to the inverse of the material color,
+    /// so a blue glass absorbs red and green the further light travels inside of it
+    pub const fn with_absorption(mut self, absorption: f32) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    /// Makes the material glow with the given radiance
+    pub const fn with_emission(mut self, emission: Rgb) -> Self {
+        self.emission = emission;
+        self
+    }
+
+    /// Takes the color from the texture, tinted by the material color
+    pub fn with_color_texture(mut self, texture: impl Into<Texture>) -> Self {
+        self.color_texture = Some(texture.into());
+        self
+    }
+
+    /// Takes the shininess from the texture luminance scaled by max_shininess
+    pub fn with_shininess_texture(
+        mut self,
+        texture: impl Into<Texture>,
+        max_shininess: f32,
+    ) -> Self {
+        self.shininess = Some(max_shininess);
+        self.shininess_texture = Some(texture.into());
+        self
+    }
+
+    /// Takes the reflectiveness from the texture luminance
+    pub fn with_reflectiveness_texture(mut self, texture: impl Into<Texture>) -> Self {
+        self.reflectiveness = Some(1.0);
+        self.reflectiveness_texture = Some(texture.into());
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
     #[inline]
-    pub fn get_color(&self) -> Color {
+    pub fn get_normal_map(&self) -> Option<&NormalMap> {
+        self.normal_map.as_ref()
+    }
+
+    /// Applies the normal map (if any) to the geometric normal
+    pub fn shading_normal(&self, normal: DVec3, tangent: DVec3, point: &TexturePoint) -> DVec3 {
+        match &self.normal_map {
+            Some(normal_map) => normal_map.apply(normal, tangent, point),
+            None => normal,
+        }
+    }
+
+    #[inline]
+    pub fn has_textures(&self) -> bool {
+        self.color_texture.is_some()
+            || self.shininess_texture.is_some()
+            || self.reflectiveness_texture.is_some()
+            || self.normal_map.is_some()
+    }
+
+    /// Resolves any textured values at the given point
+    ///
+    /// The returned material only has constant values, untextured materials are borrowed as is
+    pub fn resolve(&self, point: &TexturePoint) -> Cow<'_, Self> {
+        if !self.has_textures() {
+            return Cow::Borrowed(self);
+        }
+
+        // spelled out so the textures themselves aren't cloned just to be dropped
+        let mut material = Self {
+            id: self.id,
+            color: self.color,
+            shading: self.shading,
+            shininess: self.shininess,
+            reflectiveness: self.reflectiveness,
+            transparency: self.transparency,
+            refractive_index: self.refractive_index,
+            dispersion: self.dispersion,
+            absorption: self.absorption,
+            emission: self.emission,
+            color_texture: None,
+            shininess_texture: None,
+            reflectiveness_texture: None,
+            normal_map: None,
+        };
+
+        if let Some(texture) = &self.color_texture {
+            material.color = self.color * texture.sample(point);
+        }
+
+        if let Some(texture) = &self.shininess_texture {
+            material.shininess = self
+                .shininess
+                .map(|shininess| shininess * texture.sample(point).luminance());
+        }
+
+        if let Some(texture) = &self.reflectiveness_texture {
+            material.reflectiveness = self
+                .reflectiveness
+                .map(|reflectiveness| reflectiveness * texture.sample(point).luminance());
+        }
+
+        Cow::Owned(material)
+    }
+
+    #[inline]
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    #[inline]
+    pub fn get_color(&self) -> Rgb {
         self.color
     }
 
+    #[inline]
+    pub fn get_shading(&self) -> ShadingModel {
+        self.shading
+    }
+
     #[inline]
     pub fn get_shininess(&self) -> Option<f32> {
         self.shininess
@@ -49,13 +252,95 @@ impl Material {
     pub fn get_reflectiveness(&self) -> Option<f32> {
         self.reflectiveness
     }
+
+    #[inline]
+    pub fn get_transparency(&self) -> Option<f32> {
+        self.transparency
+    }
+
+    #[inline]
+    pub fn get_refractive_index(&self) -> f32 {
+        self.refractive_index
+    }
+
+    #[inline]
+    pub fn get_dispersion(&self) -> Option<Dispersion> {
+        self.dispersion
+    }
+
+    #[inline]
+    pub fn get_absorption(&self) -> f32 {
+        self.absorption
+    }
+
+    #[inline]
+    pub fn get_emission(&self) -> Rgb {
+        self.emission
+    }
+}
+
+/// How the center of a shape moves over time
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Motion {
+    #[default]
+    Static,
+
+    /// Moves in a straight line to the given center between time 0 and 1
+    Linear(Vec3),
+
+    /// (time, center) pairs sorted by time, held at the ends
+    Keyframes(Vec<(f32, Vec3)>),
+}
+
+impl Motion {
+    #[inline]
+    pub fn is_static(&self) -> bool {
+        match self {
+            Self::Static => true,
+            Self::Linear(_) => false,
+            Self::Keyframes(keyframes) => keyframes.len() < 2,
+        }
+    }
+
+    /// Center at the given time for a shape starting at the given center
+    pub fn evaluate(&self, start: Vec3, time: f32) -> Vec3 {
+        match self {
+            Self::Static => start,
+            Self::Linear(end) => start.lerp(*end, time.clamp(0.0, 1.0)),
+            Self::Keyframes(keyframes) => {
+                let (first, last) = match (keyframes.first(), keyframes.last()) {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => return start,
+                };
+
+                if time <= first.0 {
+                    return first.1;
+                }
+
+                keyframes
+                    .windows(2)
+                    .find(|w| time <= w[1].0)
+                    .map_or(last.1, |w| {
+                        let (t0, c0) = w[0];
+                        let (t1, c1) = w[1];
+                        let dt = t1 - t0;
+                        if dt <= 0.0 {
+                            c1
+                        } else {
+                            c0.lerp(c1, (time - t0) / dt)
+                        }
+                    })
+            }
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Sphere {
     center: Vec3,
     radius: f32,
     material: Material,
+    motion: Motion,
 }
 
 impl Default for Sphere {
@@ -64,6 +349,7 @@ impl Default for Sphere {
             center: Vec3::default(),
             radius: 1.0,
             material: Material::default(),
+            motion: Motion::Static,
         }
     }
 }
@@ -74,28 +360,81 @@ impl Sphere {
             center,
             radius,
             material,
+            motion: Motion::Static,
         }
     }
 
+    pub fn with_motion(mut self, motion: Motion) -> Self {
+        self.motion = motion;
+        self
+    }
+
+    #[inline]
+    pub fn get_motion(&self) -> &Motion {
+        &self.motion
+    }
+
+    /// Center at the start of the motion
     #[inline]
     pub fn get_center(&self) -> Vec3 {
         self.center
     }
 
+    /// Center after moving for the given time
+    #[inline]
+    pub fn get_center_at(&self, time: f32) -> Vec3 {
+        self.motion.evaluate(self.center, time)
+    }
+
     #[inline]
     pub fn get_radius(&self) -> f32 {
         self.radius
     }
 
     #[inline]
-    pub fn get_material(&self) -> Material {
-        self.material
+    pub fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    #[inline]
+    pub fn get_normal(&self, point: DVec3, time: f32) -> DVec3 {
+        (point - self.get_center_at(time).as_dvec3()).normalize_or_zero()
+    }
+
+    /// Direction of increasing u along the surface
+    pub fn get_tangent(&self, point: DVec3, time: f32) -> DVec3 {
+        let n = self.get_normal(point, time);
+
+        let tangent = DVec3::new(-n.z, 0.0, n.x).normalize_or_zero();
+        if tangent == DVec3::ZERO {
+            // u is undefined at the poles
+            orthonormal_basis(n).0
+        } else {
+            tangent
+        }
+    }
+
+    /// Spherical (longitude / latitude) texture coordinates
+    pub fn get_uv(&self, point: DVec3, time: f32) -> Vec2 {
+        let d = self.get_normal(point, time);
+
+        let u = 0.5 + d.z.atan2(d.x) / (2.0 * PI);
+        let v = 0.5 + d.y.clamp(-1.0, 1.0).asin() / PI;
+        Vec2::new(u as f32, v as f32)
+    }
+
+    pub fn get_texture_point(&self, point: DVec3, time: f32) -> TexturePoint {
+        TexturePoint {
+            uv: self.get_uv(point, time),
+            object: (point - self.get_center_at(time).as_dvec3()).as_vec3(),
+            world: point.as_vec3(),
+        }
     }
 
     // page 20 - 22
-    fn intersect_ray(&self, origin: DVec3, direction: DVec3) -> Option<(f64, f64)> {
+    fn intersect_ray(&self, origin: DVec3, direction: DVec3, time: f32) -> Option<(f64, f64)> {
         let r = self.radius as f64;
-        let co = origin - self.center.as_dvec3();
+        let co = origin - self.get_center_at(time).as_dvec3();
 
         let a = direction.dot(direction);
         let b = 2.0 * co.dot(direction);
@@ -128,6 +467,20 @@ impl Shape {
         Self::Sphere(Sphere::new(center, radius, material))
     }
 
+    pub const fn new_moving_sphere(
+        center: Vec3,
+        radius: f32,
+        material: Material,
+        motion: Motion,
+    ) -> Self {
+        Self::Sphere(Sphere {
+            center,
+            radius,
+            material,
+            motion,
+        })
+    }
+
     #[inline]
     pub fn get_center(&self) -> Vec3 {
         match self {
@@ -136,21 +489,61 @@ impl Shape {
     }
 
     #[inline]
-    pub fn get_material(&self) -> Material {
+    pub fn get_center_at(&self, time: f32) -> Vec3 {
+        match self {
+            Self::Sphere(sphere) => sphere.get_center_at(time),
+        }
+    }
+
+    #[inline]
+    pub fn get_material(&self) -> &Material {
         match self {
             Self::Sphere(sphere) => sphere.get_material(),
         }
     }
 
+    /// Outward facing surface normal at a point on the shape at the given time
     #[inline]
-    pub fn intersect_ray(&self, origin: DVec3, direction: DVec3) -> Option<(f64, f64)> {
+    pub fn get_normal(&self, point: DVec3, time: f32) -> DVec3 {
         match self {
-            Self::Sphere(sphere) => sphere.intersect_ray(origin, direction),
+            Self::Sphere(sphere) => sphere.get_normal(point, time),
+        }
+    }
+
+    /// Surface tangent at a point on the shape, following increasing u
+    #[inline]
+    pub fn get_tangent(&self, point: DVec3, time: f32) -> DVec3 {
+        match self {
+            Self::Sphere(sphere) => sphere.get_tangent(point, time),
+        }
+    }
+
+    /// Texture coordinates at a point on the shape
+    #[inline]
+    pub fn get_uv(&self, point: DVec3, time: f32) -> Vec2 {
+        match self {
+            Self::Sphere(sphere) => sphere.get_uv(point, time),
+        }
+    }
+
+    /// Everything needed to sample a texture at a point on the shape
+    #[inline]
+    pub fn get_texture_point(&self, point: DVec3, time: f32) -> TexturePoint {
+        match self {
+            Self::Sphere(sphere) => sphere.get_texture_point(point, time),
+        }
+    }
+
+    /// Ray parameters where the ray crosses the shape, moved to where it is at the given time
+    #[inline]
+    pub fn intersect_ray(&self, origin: DVec3, direction: DVec3, time: f32) -> Option<(f64, f64)> {
+        match self {
+            Self::Sphere(sphere) => sphere.intersect_ray(origin, direction, time),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Triangle {
     vertices: [usize; 3],
     material: Material,
@@ -179,8 +572,8 @@ impl Triangle {
     }
 
     #[inline]
-    pub fn get_material(&self) -> Material {
-        self.material
+    pub fn get_material(&self) -> &Material {
+        &self.material
     }
 
     // projected should be the triangle vertices in viewport space
@@ -191,7 +584,7 @@ impl Triangle {
             projected[self.vertices[0]],
             projected[self.vertices[1]],
             projected[self.vertices[2]],
-            self.material.get_color(),
+            self.material.get_color().into(),
         )?;
 
         Ok(())
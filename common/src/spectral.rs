@@ -0,0 +1,161 @@
+use std::sync::OnceLock;
+
+use glam::{Mat3, Vec3};
+use rand::Rng;
+
+use crate::color::*;
+
+/// Shortest sampled wavelength in nanometers
+pub const WAVELENGTH_MIN: f32 = 380.0;
+
+/// Longest sampled wavelength in nanometers
+pub const WAVELENGTH_MAX: f32 = 780.0;
+
+/// Wavelength dependent index of refraction
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / λ², λ in micrometers
+    Cauchy { a: f32, b: f32 },
+
+    /// n² = 1 + Σ b λ² / (λ² - c), λ in micrometers
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Dispersion {
+    /// Schott BK7 crown glass
+    pub const BK7: Self = Self::sellmeier(
+        [1.039_612, 0.231_792_34, 1.010_469_5],
+        [0.006_000_699, 0.020_017_914, 103.560_65],
+    );
+
+    /// Diamond, which is what makes it sparkle
+    pub const DIAMOND: Self = Self::sellmeier([0.3306, 4.3356, 0.0], [0.030_625, 0.011_236, 0.0]);
+
+    /// Water at room temperature
+    pub const WATER: Self = Self::cauchy(1.3242, 0.003_073);
+
+    pub const fn cauchy(a: f32, b: f32) -> Self {
+        Self::Cauchy { a, b }
+    }
+
+    pub const fn sellmeier(b: [f32; 3], c: [f32; 3]) -> Self {
+        Self::Sellmeier { b, c }
+    }
+
+    /// Index of refraction at the given wavelength in nanometers
+    pub fn get_refractive_index(&self, wavelength: f32) -> f32 {
+        let l2 = (wavelength * 0.001).powi(2);
+        match *self {
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => {
+                let n2 = 1.0 + b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum::<f32>();
+                n2.max(1.0).sqrt()
+            }
+        }
+    }
+}
+
+/// Uniformly samples a visible wavelength
+pub fn sample_wavelength(rng: &mut impl Rng) -> f32 {
+    rng.gen_range(WAVELENGTH_MIN..WAVELENGTH_MAX)
+}
+
+/// Piecewise gaussian used by the color matching function fit
+#[inline]
+fn lobe(wavelength: f32, mean: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let sigma = if wavelength < mean {
+        sigma_low
+    } else {
+        sigma_high
+    };
+    let t = (wavelength - mean) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 2° color matching functions at the given wavelength in nanometers
+///
+/// Uses the multi-lobe fit from Wyman, Sloan and Shirley 2013
+pub fn cie_xyz(wavelength: f32) -> Vec3 {
+    let l = wavelength;
+    Vec3::new(
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    )
+}
+
+/// CIE XYZ to linear sRGB (D65)
+pub fn xyz_to_rgb(xyz: Vec3) -> Rgb {
+    const XYZ_TO_RGB: Mat3 = Mat3::from_cols_array(&[
+        3.240_454_2,
+        -0.969_266,
+        0.055_643_4,
+        -1.537_138_5,
+        1.876_010_8,
+        -0.204_025_9,
+        -0.498_531_4,
+        0.041_556,
+        1.057_225_2,
+    ]);
+
+    let rgb = XYZ_TO_RGB * xyz;
+    Rgb::new(rgb.x, rgb.y, rgb.z)
+}
+
+/// Linear sRGB weight of a single uniformly sampled wavelength
+///
+/// This is an approximation, the scene is still lit and shaded in RGB and only refraction
+/// sees the wavelength. The weights average to white over the visible range so non
+/// dispersive paths converge to the RGB image, but they go negative where the color matching
+/// functions fall outside of the sRGB gamut, so single samples can carry negative radiance
+pub fn spectral_weight(wavelength: f32) -> Rgb {
+    static NORMALIZATION: OnceLock<Rgb> = OnceLock::new();
+
+    let normalization = NORMALIZATION.get_or_init(|| {
+        let steps = (WAVELENGTH_MAX - WAVELENGTH_MIN) as usize;
+        let sum: Rgb = (0..steps)
+            .map(|step| xyz_to_rgb(cie_xyz(WAVELENGTH_MIN + step as f32 + 0.5)))
+            .sum();
+        sum / steps as f32
+    });
+
+    let rgb = xyz_to_rgb(cie_xyz(wavelength));
+    Rgb::new(
+        rgb.r / normalization.r,
+        rgb.g / normalization.g,
+        rgb.b / normalization.b,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bk7_matches_the_catalog_index() {
+        // n_d at the helium d line
+        let n = Dispersion::BK7.get_refractive_index(587.6);
+        assert!((n - 1.5168).abs() < 1e-4, "{}", n);
+
+        // and shorter wavelengths bend more
+        assert!(Dispersion::BK7.get_refractive_index(450.0) > n);
+        assert!(Dispersion::BK7.get_refractive_index(700.0) < n);
+    }
+
+    #[test]
+    fn spectral_weights_average_to_white() {
+        let steps = 4000;
+        let sum: Rgb = (0..steps)
+            .map(|step| {
+                let t = (step as f32 + 0.5) / steps as f32;
+                spectral_weight(WAVELENGTH_MIN + t * (WAVELENGTH_MAX - WAVELENGTH_MIN))
+            })
+            .sum();
+        let mean = sum / steps as f32;
+
+        for channel in [mean.r, mean.g, mean.b] {
+            assert!((channel - 1.0).abs() < 1e-2, "{:?}", mean);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+use crate::color::*;
+use crate::framebuffer::*;
+
+/// Ray and intersection counts
+///
+/// Shapes are tested linearly (there's no acceleration structure yet)
+/// so intersection tests are the best measure of the work done.
+/// Without a BVH there are no node visits to count either
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RayStats {
+    pub primary_rays: u64,
+    pub shadow_rays: u64,
+    pub reflection_rays: u64,
+    pub refraction_rays: u64,
+    pub intersection_tests: u64,
+}
+
+impl RayStats {
+    #[inline]
+    pub fn get_total_rays(&self) -> u64 {
+        self.primary_rays + self.shadow_rays + self.reflection_rays + self.refraction_rays
+    }
+
+    pub fn get_rays_per_second(&self, elapsed: Duration) -> f64 {
+        let seconds = elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        self.get_total_rays() as f64 / seconds
+    }
+
+    /// One line summary for a frame that took the given time
+    pub fn report(&self, elapsed: Duration) -> String {
+        format!(
+            "{} in {:.2?} ({:.2} Mrays/s)",
+            self,
+            elapsed,
+            self.get_rays_per_second(elapsed) / 1_000_000.0
+        )
+    }
+}
+
+impl fmt::Display for RayStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rays ({} primary, {} shadow, {} reflection, {} refraction), {} intersection tests",
+            self.get_total_rays(),
+            self.primary_rays,
+            self.shadow_rays,
+            self.reflection_rays,
+            self.refraction_rays,
+            self.intersection_tests
+        )
+    }
+}
+
+/// Counts added between two snapshots, 0 if take_stats reset the counts in between
+impl std::ops::Sub for RayStats {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            primary_rays: self.primary_rays.saturating_sub(rhs.primary_rays),
+            shadow_rays: self.shadow_rays.saturating_sub(rhs.shadow_rays),
+            reflection_rays: self.reflection_rays.saturating_sub(rhs.reflection_rays),
+            refraction_rays: self.refraction_rays.saturating_sub(rhs.refraction_rays),
+            intersection_tests: self
+                .intersection_tests
+                .saturating_sub(rhs.intersection_tests),
+        }
+    }
+}
+
+thread_local! {
+    // per thread so counting stays cheap and measure() isn't confused by other threads
+    static STATS: Cell<RayStats> = Cell::new(RayStats::default());
+}
+
+#[inline]
+pub(crate) fn record(f: impl FnOnce(&mut RayStats)) {
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        f(&mut current);
+        stats.set(current);
+    });
+}
+
+/// Counts gathered on this thread so far
+pub fn get_stats() -> RayStats {
+    STATS.with(Cell::get)
+}
+
+/// Returns the counts gathered on this thread and starts over
+pub fn take_stats() -> RayStats {
+    STATS.with(|stats| stats.replace(RayStats::default()))
+}
+
+/// Runs f and returns its result with the counts it added
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, RayStats) {
+    let before = get_stats();
+    let result = f();
+    (result, get_stats() - before)
+}
+
+/// Per-pixel cost (intersection tests) for false-color heatmaps
+#[derive(Debug, Clone)]
+pub struct CostMap {
+    half_width: i32,
+    half_height: i32,
+
+    costs: Vec<u64>,
+}
+
+impl CostMap {
+    pub fn new(half_width: i32, half_height: i32) -> Self {
+        let width = (half_width * 2 + 1) as usize;
+        let height = (half_height * 2 + 1) as usize;
+
+        Self {
+            half_width,
+            half_height,
+            costs: vec![0; width * height],
+        }
+    }
+
+    pub fn from_framebuffer(framebuffer: &Framebuffer) -> Self {
+        Self::new(framebuffer.get_half_width(), framebuffer.get_half_height())
+    }
+
+    #[inline]
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x.abs() > self.half_width || y.abs() > self.half_height {
+            return None;
+        }
+
+        let row = (self.half_height - y) as usize;
+        let col = (self.half_width + x) as usize;
+        Some(row * (self.half_width * 2 + 1) as usize + col)
+    }
+
+    #[inline]
+    pub fn get_cost(&self, x: i32, y: i32) -> u64 {
+        self.index(x, y).map_or(0, |idx| self.costs[idx])
+    }
+
+    /// Adds the cost of a (sub-pixel) sample to the pixel it falls in
+    pub fn add(&mut self, x: f32, y: f32, stats: &RayStats) {
+        if let Some(idx) = self.index(x.round() as i32, y.round() as i32) {
+            self.costs[idx] += stats.intersection_tests;
+        }
+    }
+
+    /// Writes the costs to the framebuffer as blue (cheap) to red (expensive)
+    pub fn render(&self, framebuffer: &mut Framebuffer) {
+        let max = self.costs.iter().copied().max().unwrap_or(0).max(1) as f32;
+
+        for x in -self.half_width..=self.half_width {
+            for y in -self.half_height..=self.half_height {
+                let t = self.get_cost(x, y) as f32 / max;
+                framebuffer.put_pixel(x, y, heat(t));
+            }
+        }
+    }
+}
+
+/// Blue - cyan - green - yellow - red ramp
+fn heat(t: f32) -> Rgb {
+    const RAMP: [Rgb; 5] = [Rgb::BLUE, Rgb::CYAN, Rgb::GREEN, Rgb::YELLOW, Rgb::RED];
+
+    let t = t.clamp(0.0, 1.0) * (RAMP.len() - 1) as f32;
+    let i = (t.floor() as usize).min(RAMP.len() - 2);
+    RAMP[i].lerp(RAMP[i + 1], t - i as f32)
+}
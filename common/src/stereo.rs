@@ -0,0 +1,117 @@
+use crate::camera::*;
+use crate::color::*;
+use crate::framebuffer::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    pub const BOTH: [Self; 2] = [Self::Left, Self::Right];
+}
+
+/// Pair of off-axis cameras built around a center camera
+#[derive(Debug, Clone)]
+pub struct StereoRig {
+    camera: Camera,
+    interocular: f32,
+    convergence: f32,
+}
+
+impl StereoRig {
+    /// Objects at the convergence distance appear at the screen plane,
+    /// closer ones pop out of it and further ones sink behind it
+    pub fn new(camera: Camera, interocular: f32, convergence: f32) -> Self {
+        Self {
+            camera,
+            interocular,
+            convergence,
+        }
+    }
+
+    #[inline]
+    pub fn get_camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    #[inline]
+    pub fn get_interocular(&self) -> f32 {
+        self.interocular
+    }
+
+    #[inline]
+    pub fn get_convergence(&self) -> f32 {
+        self.convergence
+    }
+
+    /// Camera for the given eye, usable by both the raytracer and the rasterizer
+    pub fn get_eye(&self, eye: Eye) -> Camera {
+        let offset = match eye {
+            Eye::Left => -0.5 * self.interocular,
+            Eye::Right => 0.5 * self.interocular,
+        };
+
+        self.camera.clone().with_eye(offset, self.convergence)
+    }
+}
+
+/// How both eye images are packed into one output image
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// Left eye on the left half, both squeezed horizontally (half side-by-side)
+    #[default]
+    SideBySide,
+
+    /// Left eye on the top half, both squeezed vertically
+    TopBottom,
+
+    /// Left eye in the red channel, right eye in green and blue
+    Anaglyph,
+}
+
+impl StereoLayout {
+    /// Packs the full size eye images into the output
+    pub fn compose(&self, left: &Framebuffer, right: &Framebuffer, output: &mut Framebuffer) {
+        let half_width = output.get_half_width();
+        let half_height = output.get_half_height();
+        assert!([left, right]
+            .iter()
+            .all(|eye| eye.get_half_width() == half_width && eye.get_half_height() == half_height));
+
+        for x in -half_width..=half_width {
+            for y in -half_height..=half_height {
+                let color = match self {
+                    Self::SideBySide => {
+                        let (eye, ex) = if x < 0 {
+                            (left, 2 * x + half_width)
+                        } else {
+                            (right, 2 * x - half_width)
+                        };
+
+                        // average the two columns squeezed into this one
+                        let next = (ex + 1).min(half_width);
+                        (eye.get_pixel(ex, y) + eye.get_pixel(next, y)) * 0.5
+                    }
+                    Self::TopBottom => {
+                        let (eye, ey) = if y >= 0 {
+                            (left, 2 * y - half_height)
+                        } else {
+                            (right, 2 * y + half_height)
+                        };
+
+                        let next = (ey - 1).max(-half_height);
+                        (eye.get_pixel(x, ey) + eye.get_pixel(x, next)) * 0.5
+                    }
+                    Self::Anaglyph => {
+                        let (l, r) = (left.get_pixel(x, y), right.get_pixel(x, y));
+                        Rgb::new(l.r, r.g, r.b)
+                    }
+                };
+
+                output.put_pixel(x, y, color);
+            }
+        }
+    }
+}
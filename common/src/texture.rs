@@ -0,0 +1,380 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{DVec3, Vec2, Vec3};
+
+use crate::{ProceduralTexture, Rgb};
+
+/// Where a texture is being sampled on a surface
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TexturePoint {
+    pub uv: Vec2,
+
+    /// Position relative to the shape
+    pub object: Vec3,
+
+    pub world: Vec3,
+}
+
+/// How texture lookups are filtered
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// How texture coordinates outside of [0, 1] are handled
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    /// Wraps an integer texel coordinate into [0, size)
+    fn apply(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        match self {
+            Self::Repeat => i.rem_euclid(size) as usize,
+            Self::Clamp => i.clamp(0, size - 1) as usize,
+            Self::Mirror => {
+                let period = i.rem_euclid(size * 2);
+                if period < size {
+                    period as usize
+                } else {
+                    (size * 2 - 1 - period) as usize
+                }
+            }
+        }
+    }
+}
+
+impl TexturePoint {
+    /// Moves the point across the surface, by (du, dv) in texture space
+    /// and by distance along the tangent / bitangent in object / world space
+    fn offset(&self, du: f32, dv: f32, offset: DVec3) -> Self {
+        let offset = offset.as_vec3();
+        Self {
+            uv: self.uv + Vec2::new(du, dv),
+            object: self.object + offset,
+            world: self.world + offset,
+        }
+    }
+}
+
+/// Image texture sampled with (u, v) coordinates, v = 0 is the bottom of the image
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+
+    // rows stored top to bottom as in the source image
+    pixels: Vec<Rgb>,
+
+    filter: TextureFilter,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Rgb>) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            anyhow::bail!(
+                "invalid texture size {}x{} for {} pixels",
+                width,
+                height,
+                pixels.len()
+            );
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            filter: TextureFilter::default(),
+            wrap: WrapMode::default(),
+        })
+    }
+
+    /// Loads a .png or .ppm image
+    ///
+    /// Channel values are used as-is, no sRGB decoding is done
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => Self::load_png(path),
+            Some("ppm") => Self::load_ppm(path),
+            _ => anyhow::bail!("unsupported texture format: {}", path.display()),
+        }
+    }
+
+    fn load_png(path: &Path) -> anyhow::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        // expand palettes and strip 16-bit channels so we only deal with 8-bit gray / rgb
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let buf = &buf[..info.buffer_size()];
+
+        let channels = info.color_type.samples();
+        let pixels = buf
+            .chunks_exact(channels)
+            .map(|c| match channels {
+                1 | 2 => Rgb::from_rgb8(c[0], c[0], c[0]),
+                _ => Rgb::from_rgb8(c[0], c[1], c[2]),
+            })
+            .collect();
+
+        Self::new(info.width as usize, info.height as usize, pixels)
+    }
+
+    fn load_ppm(path: &Path) -> anyhow::Result<Self> {
+        let mut data = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut data)?;
+
+        Self::from_ppm(&data)
+    }
+
+    /// Parses a binary (P6) or ASCII (P3) PPM image
+    pub fn from_ppm(data: &[u8]) -> anyhow::Result<Self> {
+        let mut pos = 0;
+
+        // header tokens are whitespace separated with # comments
+        let mut next_token = || -> anyhow::Result<String> {
+            loop {
+                while pos < data.len() && data[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+
+                if pos < data.len() && data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                    continue;
+                }
+
+                break;
+            }
+
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+
+            if start == pos {
+                anyhow::bail!("unexpected end of PPM data");
+            }
+
+            Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+        };
+
+        let magic = next_token()?;
+        let width: usize = next_token()?.parse()?;
+        let height: usize = next_token()?.parse()?;
+        let max_value: u32 = next_token()?.parse()?;
+        if max_value == 0 || max_value > 65535 {
+            anyhow::bail!("invalid PPM max value {}", max_value);
+        }
+
+        let scale = 1.0 / max_value as f32;
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| anyhow::anyhow!("PPM size {}x{} is too large", width, height))?;
+
+        let values: Vec<u32> = match magic.as_str() {
+            "P3" => (0..count)
+                .map(|_| Ok(next_token()?.parse()?))
+                .collect::<anyhow::Result<_>>()?,
+            "P6" => {
+                // a single whitespace byte separates the header from the raster
+                let start = pos + 1;
+                let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+                let raster = count
+                    .checked_mul(bytes_per_value)
+                    .and_then(|len| data.get(start..start.checked_add(len)?))
+                    .ok_or_else(|| anyhow::anyhow!("truncated PPM raster"))?;
+
+                if bytes_per_value == 1 {
+                    raster.iter().map(|v| *v as u32).collect()
+                } else {
+                    raster
+                        .chunks_exact(2)
+                        .map(|v| u16::from_be_bytes([v[0], v[1]]) as u32)
+                        .collect()
+                }
+            }
+            _ => anyhow::bail!("unsupported PPM type {}", magic),
+        };
+
+        let pixels = values
+            .chunks_exact(3)
+            .map(|c| Rgb::new(c[0] as f32, c[1] as f32, c[2] as f32) * scale)
+            .collect();
+
+        Self::new(width, height, pixels)
+    }
+
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    #[inline]
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    pub fn get_filter(&self) -> TextureFilter {
+        self.filter
+    }
+
+    #[inline]
+    pub fn get_wrap(&self) -> WrapMode {
+        self.wrap
+    }
+
+    #[inline]
+    fn texel(&self, x: i64, y: i64) -> Rgb {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+
+        // flip so v = 0 is the bottom row
+        self.pixels[(self.height - 1 - y) * self.width + x]
+    }
+
+    pub fn sample(&self, uv: Vec2) -> Rgb {
+        // texel centers are at half-integer coordinates
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+
+        match self.filter {
+            TextureFilter::Nearest => self.texel(x.round() as i64, y.round() as i64),
+            TextureFilter::Bilinear => {
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let tx = x - x0;
+                let ty = y - y0;
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let bottom = self.texel(x0, y0).lerp(self.texel(x0 + 1, y0), tx);
+                let top = self.texel(x0, y0 + 1).lerp(self.texel(x0 + 1, y0 + 1), tx);
+                bottom.lerp(top, ty)
+            }
+        }
+    }
+}
+
+/// A source of varying material values across a surface
+#[derive(Debug, Clone)]
+pub enum Texture {
+    Image(Arc<ImageTexture>),
+    Procedural(ProceduralTexture),
+}
+
+impl From<ImageTexture> for Texture {
+    fn from(texture: ImageTexture) -> Self {
+        Self::Image(Arc::new(texture))
+    }
+}
+
+impl From<ProceduralTexture> for Texture {
+    fn from(texture: ProceduralTexture) -> Self {
+        Self::Procedural(texture)
+    }
+}
+
+impl Texture {
+    pub fn sample(&self, point: &TexturePoint) -> Rgb {
+        match self {
+            Self::Image(texture) => texture.sample(point.uv),
+            Self::Procedural(texture) => texture.sample(point),
+        }
+    }
+}
+
+/// Step used for the finite difference gradient of bump maps
+const BUMP_EPSILON: f64 = 1e-3;
+
+/// Perturbs the shading normal to add surface detail without extra geometry
+#[derive(Debug, Clone)]
+pub enum NormalMap {
+    /// Height map (texture luminance) whose gradient tilts the normal
+    Bump { height: Texture, strength: f32 },
+
+    /// RGB encoded tangent-space normals, (0.5, 0.5, 1.0) is unperturbed
+    TangentSpace { normals: Texture, strength: f32 },
+}
+
+impl NormalMap {
+    pub const fn bump(height: Texture, strength: f32) -> Self {
+        Self::Bump { height, strength }
+    }
+
+    pub const fn tangent_space(normals: Texture, strength: f32) -> Self {
+        Self::TangentSpace { normals, strength }
+    }
+
+    /// Returns the perturbed shading normal
+    ///
+    /// normal and tangent should be normalized and perpendicular
+    pub fn apply(&self, normal: DVec3, tangent: DVec3, point: &TexturePoint) -> DVec3 {
+        let bitangent = tangent.cross(normal);
+
+        let n = match self {
+            Self::Bump { height, strength } => {
+                let eps = BUMP_EPSILON as f32;
+
+                let h = height.sample(point).luminance();
+                let hu = height
+                    .sample(&point.offset(eps, 0.0, tangent * BUMP_EPSILON))
+                    .luminance();
+                let hv = height
+                    .sample(&point.offset(0.0, eps, bitangent * BUMP_EPSILON))
+                    .luminance();
+
+                let dhdu = ((hu - h) / eps * strength) as f64;
+                let dhdv = ((hv - h) / eps * strength) as f64;
+
+                normal - dhdu * tangent - dhdv * bitangent
+            }
+            Self::TangentSpace { normals, strength } => {
+                let c = normals.sample(point);
+                let x = ((c.r * 2.0 - 1.0) * strength) as f64;
+                let y = ((c.g * 2.0 - 1.0) * strength) as f64;
+                let z = (c.b * 2.0 - 1.0) as f64;
+
+                x * tangent + y * bitangent + z * normal
+            }
+        };
+
+        let n = n.normalize_or_zero();
+        if n == DVec3::ZERO {
+            normal
+        } else {
+            n
+        }
+    }
+}
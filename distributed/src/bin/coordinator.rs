@@ -0,0 +1,73 @@
+use std::net::TcpListener;
+use std::time::Instant;
+
+use glam::Vec3;
+
+use common::*;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+const DEFAULT_OUTPUT: &str = "render.hdr";
+
+const HALF_WIDTH: i32 = 540;
+const HALF_HEIGHT: i32 = 540;
+
+const REFLECT_DEPTH: usize = 3;
+const SAMPLES: usize = 4;
+
+// same scene as chapter 4, without the floor texture
+fn job() -> RenderJob {
+    RenderJob {
+        half_width: HALF_WIDTH,
+        half_height: HALF_HEIGHT,
+        camera: Camera::default(),
+        shapes: vec![
+            Shape::new_sphere(
+                Vec3::new(0.0, -1.0, 3.0),
+                1.0,
+                Material::new(Rgb::RED, Some(500.0), Some(0.2)),
+            ),
+            Shape::new_sphere(
+                Vec3::new(2.0, 0.0, 4.0),
+                1.0,
+                Material::new(Rgb::BLUE, Some(500.0), Some(0.3)),
+            ),
+            Shape::new_sphere(
+                Vec3::new(-2.0, 0.0, 4.0),
+                1.0,
+                Material::new(Rgb::GREEN, Some(10.0), Some(0.4)),
+            ),
+            Shape::new_sphere(
+                Vec3::new(0.0, -5001.0, 0.0),
+                5000.0,
+                Material::new(Rgb::YELLOW, Some(1000.0), Some(0.5)),
+            ),
+        ],
+        lights: vec![
+            Light::new_ambient(Rgb::splat(0.2)),
+            Light::new_point(Rgb::splat(0.6), Vec3::new(2.0, 1.0, 0.0)),
+            Light::new_directional(Rgb::splat(0.2), Vec3::new(1.0, 4.0, 4.0)),
+        ],
+        background: Environment::color(Rgb::BLACK),
+        reflection_depth: REFLECT_DEPTH,
+        samples: SAMPLES,
+    }
+}
+
+/// coordinator [address] [output.hdr]
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+    let output = args.next().unwrap_or_else(|| DEFAULT_OUTPUT.to_owned());
+
+    let listener = TcpListener::bind(&address)?;
+    println!("waiting for workers on {}", listener.local_addr()?);
+
+    let start = Instant::now();
+    let framebuffer = Coordinator::new(job()).run(listener)?;
+    println!("rendered in {:.2?}", start.elapsed());
+
+    framebuffer.write_hdr(&output)?;
+    println!("wrote {}", output);
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use common::*;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+/// worker [coordinator address]
+fn main() -> anyhow::Result<()> {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+
+    println!("connecting to {}", address);
+    run_worker(&address)?;
+    println!("done");
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use glam::Vec3;
+
+use common::*;
+
+const WINDOW_TITLE: &str = "Path Tracer";
+
+// same scene as chapter 4
+const SPHERES: &[Shape] = &[
+    Shape::new_sphere(
+        Vec3::new(0.0, -1.0, 3.0),
+        1.0,
+        Material::new(Rgb::RED, Some(500.0), Some(0.2)),
+    ),
+    Shape::new_sphere(
+        Vec3::new(2.0, 0.0, 4.0),
+        1.0,
+        Material::new(Rgb::BLUE, Some(500.0), Some(0.3)),
+    ),
+    // slides up while the shutter is open
+    Shape::new_moving_sphere(
+        Vec3::new(-2.0, 0.0, 4.0),
+        1.0,
+        Material::new(Rgb::GREEN, Some(10.0), Some(0.4)),
+        Motion::Linear(Vec3::new(-2.0, 0.5, 4.0)),
+    ),
+    // dispersive glass, splits the light into colors in spectral mode
+    Shape::new_sphere(
+        Vec3::new(1.0, -0.6, 2.2),
+        0.4,
+        Material::new(Rgb::WHITE, Some(1000.0), Some(0.05))
+            .with_transparency(0.95, 1.5168)
+            .with_dispersion(Dispersion::BK7),
+    ),
+    Shape::new_sphere(
+        Vec3::new(0.0, -5001.0, 0.0),
+        5000.0,
+        Material::new(Rgb::YELLOW, Some(1000.0), Some(0.5)),
+    ),
+];
+
+// the ambient light is ignored by the path tracer
+const LIGHTS: &[Light] = &[
+    Light::new_ambient(Rgb::splat(0.2)),
+    Light::new_point(Rgb::splat(0.6), Vec3::new(2.0, 1.0, 0.0)),
+    Light::new_directional(Rgb::splat(0.2), Vec3::new(1.0, 4.0, 4.0)),
+];
+
+const BACKGROUND: Environment = Environment::gradient(
+    Rgb::new(0.2, 0.3, 0.5),
+    Rgb::new(0.5, 0.5, 0.55),
+    Rgb::splat(0.1),
+);
+
+const MEDIA: Media = Media::new();
+
+// trace one wavelength per sample so refraction can depend on it
+const SPECTRAL: bool = true;
+
+const MAX_DEPTH: usize = 8;
+
+// one jittered sample per pixel per frame, accumulated over time
+const ANTIALIASING: Antialiasing =
+    Antialiasing::new(1, SamplePattern::Jittered, ReconstructionFilter::Box);
+
+/// Emitters are far brighter than 1.0, so clamping would blow them out
+const TONE_MAPPING: ToneMapping = ToneMapping::AcesFilmic;
+
+// filters the accumulated frame for display, the accumulation itself stays noisy
+const DENOISER: Option<Denoiser> = Some(Denoiser::new(5));
+
+fn render(
+    canvas: &Canvas,
+    accumulator: &mut Accumulator,
+    guide: &mut Option<AovBuffer>,
+) -> anyhow::Result<()> {
+    // focused on the front of the red sphere with a hexagonal aperture
+    let camera = Camera::default()
+        .with_lens(0.1, 2.0)
+        .with_bokeh(Bokeh::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        })
+        .with_shutter(0.0, 1.0);
+
+    let mut rng = rand::thread_rng();
+
+    let mut frame = Framebuffer::from_canvas(canvas)
+        .with_tone_mapping(TONE_MAPPING)
+        .with_gamma_correction(true);
+
+    // the denoiser guide is traced once, along the first frame's lens and time samples
+    let mut new_guide =
+        (DENOISER.is_some() && guide.is_none()).then(|| AovBuffer::from_canvas(canvas));
+
+    // TODO: parallelize this
+    ANTIALIASING.render(&mut frame, |x, y| {
+        let Some((origin, direction)) = camera.get_ray(canvas.to_viewport_subpixel(x, y)) else {
+            return Rgb::BLACK;
+        };
+        let time = camera.sample_time();
+
+        if let Some(new_guide) = &mut new_guide {
+            let sample = trace_aov(origin, direction, 1.0, INFINITY, time, SPHERES);
+            let px = (x.round() as i32).clamp(-canvas.get_half_width(), canvas.get_half_width());
+            let py = (y.round() as i32).clamp(-canvas.get_half_height(), canvas.get_half_height());
+            new_guide.put_sample(px, py, sample);
+        }
+
+        // each sample carries a single wavelength through the glass
+        let wavelength = SPECTRAL.then(|| sample_wavelength(&mut rng));
+
+        let color = trace_path(
+            origin,
+            direction,
+            1.0,
+            INFINITY,
+            time,
+            wavelength,
+            MAX_DEPTH,
+            LIGHTS,
+            SPHERES,
+            &BACKGROUND,
+            &MEDIA,
+            &mut rng,
+        );
+        wavelength.map_or(color, |wavelength| color * spectral_weight(wavelength))
+    });
+
+    accumulator.add_frame(&frame);
+    accumulator.resolve(&mut frame);
+
+    if new_guide.is_some() {
+        *guide = new_guide;
+    }
+
+    if let (Some(denoiser), Some(guide)) = (DENOISER, guide.as_ref()) {
+        denoiser.apply(&mut frame, guide);
+    }
+
+    frame.present(canvas)?;
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut accumulator = None;
+    let mut guide = None;
+
+    let app = App::builder()
+        .window_title(WINDOW_TITLE)
+        .window_size(1080, 1080)
+        .render(move |canvas| {
+            let accumulator = accumulator.get_or_insert_with(|| Accumulator::from_canvas(canvas));
+            render(canvas, accumulator, &mut guide)
+        })
+        .build()?;
+
+    app.run()?;
+
+    Ok(())
+}